@@ -0,0 +1,83 @@
+use criterion::BenchmarkId;
+use criterion::Throughput;
+use criterion::{criterion_group, criterion_main, Criterion};
+use intelhexes::{convert, DataWriter};
+
+/// Only touches the data, so the parser is all that is measured
+struct Checksum(u8);
+
+impl<W: std::io::Write> DataWriter<W> for Checksum {
+    fn write(&mut self, _writer: &mut W, _addr: u32, bytes: &[u8]) -> std::io::Result<()> {
+        self.0 = bytes.iter().fold(self.0, |sum, b| sum.wrapping_add(*b));
+        Ok(())
+    }
+}
+
+fn nrf_bench(c: &mut Criterion) {
+    let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+
+    let mut group = c.benchmark_group("NRF");
+    group.throughput(Throughput::Bytes(hex.len() as u64));
+    group.bench_with_input(BenchmarkId::new("convert", hex.len()), hex, |b, hex| {
+        b.iter(|| {
+            convert(&hex[..], std::io::sink(), &mut Checksum(0)).expect("to be able to parse the hex")
+        })
+    });
+    group.finish();
+}
+
+fn nina_bench(c: &mut Criterion) {
+    let hex = include_bytes!("../hex-examples/NINA-W15X-SW-4.0.0-006.hex");
+
+    let mut group = c.benchmark_group("NINA");
+    group.throughput(Throughput::Bytes(hex.len() as u64));
+    group.bench_with_input(BenchmarkId::new("convert", hex.len()), hex, |b, hex| {
+        b.iter(|| {
+            convert(&hex[..], std::io::sink(), &mut Checksum(0)).expect("to be able to parse the hex")
+        })
+    });
+    group.finish();
+}
+
+/// 1 MiB of data in records of the maximum length, where decoding the hex
+/// digits takes the largest share of the time
+fn long_records() -> Vec<u8> {
+    fn push_record(hex: &mut Vec<u8>, addr: u16, record_type: u8, data: &[u8]) {
+        let mut record = vec![data.len() as u8, (addr >> 8) as u8, addr as u8, record_type];
+        record.extend_from_slice(data);
+        record.push(0u8.wrapping_sub(record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))));
+
+        hex.push(b':');
+        hex.extend(record.iter().flat_map(|b| format!("{:02X}", b).into_bytes()));
+        hex.push(b'\n');
+    }
+
+    let data: Vec<u8> = (0..0x1_0000u32).map(|i| (i * 7) as u8).collect();
+    let mut hex = Vec::new();
+
+    for segment in 0..16u16 {
+        push_record(&mut hex, 0, 0x04, &segment.to_be_bytes());
+        for (i, chunk) in data.chunks(255).enumerate() {
+            push_record(&mut hex, (i * 255) as u16, 0x00, chunk);
+        }
+    }
+    push_record(&mut hex, 0, 0x01, &[]);
+
+    hex
+}
+
+fn long_records_bench(c: &mut Criterion) {
+    let hex = long_records();
+
+    let mut group = c.benchmark_group("LONG");
+    group.throughput(Throughput::Bytes(hex.len() as u64));
+    group.bench_with_input(BenchmarkId::new("convert", hex.len()), &hex, |b, hex| {
+        b.iter(|| {
+            convert(&hex[..], std::io::sink(), &mut Checksum(0)).expect("to be able to parse the hex")
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, nrf_bench, nina_bench, long_records_bench);
+criterion_main!(benches);
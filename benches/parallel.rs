@@ -0,0 +1,32 @@
+use criterion::BenchmarkId;
+use criterion::Throughput;
+use criterion::{criterion_group, criterion_main, Criterion};
+use intelhexes::{hex2bin_parallel, hex2bin_slice, hex2dump_parallel, hex2dump_slice, BinOptions, DumpOptions};
+
+/// Compares the sequential slice path with the parallel one
+fn nina_bench(c: &mut Criterion) {
+    let hex = include_bytes!("../hex-examples/NINA-W15X-SW-4.0.0-006.hex");
+
+    let fd = std::fs::File::create("/dev/null").expect("to be able to open /dev/null");
+    let dump_opts = DumpOptions::default();
+    let bin_opts = BinOptions::default();
+
+    let mut group = c.benchmark_group("NINA");
+    group.throughput(Throughput::Bytes(hex.len() as u64));
+    group.bench_with_input(BenchmarkId::new("hex2bin_slice", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2bin_slice(hex, fd, &bin_opts).expect("to be able to parse the hex"))
+    });
+    group.bench_with_input(BenchmarkId::new("hex2bin_parallel", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2bin_parallel(hex, fd, &bin_opts).expect("to be able to parse the hex"))
+    });
+    group.bench_with_input(BenchmarkId::new("hex2dump_slice", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2dump_slice(hex, fd, &dump_opts).expect("to be able to parse the hex"))
+    });
+    group.bench_with_input(BenchmarkId::new("hex2dump_parallel", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2dump_parallel(hex, fd, &dump_opts).expect("to be able to parse the hex"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, nina_bench);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+use criterion::BenchmarkId;
+use criterion::Throughput;
+use criterion::{criterion_group, criterion_main, Criterion};
+use intelhexes::{hex2bin_slice, hex2bin_with, hex2dump_slice, hex2dump_with, BinOptions, DumpOptions};
+
+/// Compares the Read based path with parsing straight from the slice
+fn nina_bench(c: &mut Criterion) {
+    let hex = include_bytes!("../hex-examples/NINA-W15X-SW-4.0.0-006.hex");
+
+    let fd = std::fs::File::create("/dev/null").expect("to be able to open /dev/null");
+    let dump_opts = DumpOptions::default();
+    let bin_opts = BinOptions::default();
+
+    let mut group = c.benchmark_group("NINA");
+    group.throughput(Throughput::Bytes(hex.len() as u64));
+    group.bench_with_input(BenchmarkId::new("hex2bin_read", hex.len()), &fd, |b, fd| {
+        b.iter(|| {
+            hex2bin_with(std::io::Cursor::new(hex), fd, &bin_opts).expect("to be able to parse the hex")
+        })
+    });
+    group.bench_with_input(BenchmarkId::new("hex2bin_slice", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2bin_slice(hex, fd, &bin_opts).expect("to be able to parse the hex"))
+    });
+    group.bench_with_input(BenchmarkId::new("hex2dump_read", hex.len()), &fd, |b, fd| {
+        b.iter(|| {
+            hex2dump_with(std::io::Cursor::new(hex), fd, &dump_opts).expect("to be able to parse the hex")
+        })
+    });
+    group.bench_with_input(BenchmarkId::new("hex2dump_slice", hex.len()), &fd, |b, fd| {
+        b.iter(|| hex2dump_slice(hex, fd, &dump_opts).expect("to be able to parse the hex"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, nina_bench);
+criterion_main!(benches);
@@ -0,0 +1,177 @@
+use std::io::Result;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::datawriter::DataWriter;
+use crate::parser::{Event, Parser};
+use crate::pipeline::{BinOptions, Checks, ConvertWarnings, Converter, DumpOptions, WithDataWriter};
+use crate::record::RecordError;
+
+/// Bytes read from the input at a time
+const READ_SZ: usize = 4096;
+
+/// Async counterpart of `convert`. Input is parsed by the push `Parser` as
+/// it arrives, the output of the data writer is collected in memory and
+/// written out after every read.
+pub async fn convert_async<R, W, DWR>(reader: R, writer: W, data_writer: &mut DWR) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    DWR: DataWriter<Vec<u8>>,
+{
+    convert_checked(reader, writer, data_writer, Checks::default()).await.map(|_| ())
+}
+
+async fn convert_checked<R, W, DWR>(
+    mut reader: R,
+    mut writer: W,
+    data_writer: &mut DWR,
+    checks: Checks,
+) -> Result<ConvertWarnings>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    DWR: DataWriter<Vec<u8>>,
+{
+    let mut parser = Parser::with_mode(checks.lex_mode);
+    let mut converter = Converter::new(data_writer, checks);
+    let mut buf = vec![0u8; READ_SZ];
+    let mut out = Vec::new();
+
+    /* The input following the EndOfFile record is read only to check it */
+    while !parser.is_done() || checks.data_after_eof {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+
+        for event in parser.feed(&buf[..n]) {
+            match event {
+                Event::Record(line, record) => converter.record(&mut out, line, record)?,
+                Event::Error(e) => return Err(e.into()),
+            }
+        }
+
+        writer.write_all(&out).await?;
+        out.clear();
+    }
+
+    match parser.finish() {
+        Err(e) if e.error == RecordError::MissingEndOfFile => {
+            converter.missing_eof(&mut out, e)?;
+            writer.write_all(&out).await?;
+        }
+        res => res?,
+    }
+    if let Some(e) = parser.data_after_end().filter(|_| checks.data_after_eof) {
+        return Err(e.into());
+    }
+
+    writer.flush().await?;
+    Ok(converter.into_warnings(parser.warnings()))
+}
+
+/// Boxes the data writer, which the async conversions can not be handed
+/// with its concrete type the way the others are
+struct Boxed;
+
+impl WithDataWriter<Vec<u8>> for Boxed {
+    type Output = Box<dyn DataWriter<Vec<u8>> + Send>;
+
+    fn run<DWR: DataWriter<Vec<u8>> + Send + 'static>(self, data_writer: DWR) -> Self::Output {
+        Box::new(data_writer)
+    }
+}
+
+pub async fn hex2dump_async<R, W>(reader: R, writer: W, opts: &DumpOptions) -> Result<ConvertWarnings>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    convert_checked(reader, writer, &mut opts.with_data_writer(Boxed), opts.checks()).await
+}
+
+pub async fn hex2bin_async<R, W>(reader: R, writer: W, opts: &BinOptions) -> Result<ConvertWarnings>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    convert_checked(reader, writer, &mut opts.with_data_writer(Boxed), opts.checks()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_io::*;
+    use crate::pipeline::hex2dump;
+    use tokio::io::duplex;
+
+    const HEX: &[u8] = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+
+    /// Streams `HEX` through an in-memory duplex stream in small writes
+    async fn stream_hex() -> tokio::io::DuplexStream {
+        let (mut tx, rx) = duplex(64);
+
+        tokio::spawn(async move {
+            for chunk in HEX.chunks(100) {
+                tx.write_all(chunk).await.unwrap();
+            }
+        });
+
+        rx
+    }
+
+    #[tokio::test]
+    async fn it_dumps_from_a_stream() {
+        let mut output = Vec::new();
+        hex2dump_async(stream_hex().await, &mut output, &DumpOptions::default())
+            .await
+            .unwrap();
+
+        let mut expected = Vec::new();
+        hex2dump(HEX, &mut expected).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[tokio::test]
+    async fn it_converts_to_bin_from_a_stream() {
+        let (out_tx, mut out_rx) = duplex(64);
+        let reader = tokio::spawn(async move {
+            let mut output = Vec::new();
+            out_rx.read_to_end(&mut output).await.unwrap();
+            output
+        });
+
+        hex2bin_async(stream_hex().await, out_tx, &BinOptions::default())
+            .await
+            .unwrap();
+
+        let bin = std::fs::read("test/bin/sniffer_nrf52840dk_nrf52840_7cc811f.truth").unwrap();
+        assert_eq!(reader.await.unwrap(), bin);
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_a_truncated_stream() {
+        let err = hex2bin_async(&HEX[..HEX.len() / 2], Vec::new(), &BinOptions::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn it_counts_the_lines_skipped_in_lenient_mode() {
+        let hex = include_bytes!("../test/lenient.in");
+        /* The lines after the EndOfFile record count once they are read */
+        let opts = BinOptions {
+            lex_mode: crate::LexMode::Lenient(crate::Comments::all()),
+            reject_data_after_eof: true,
+            ..BinOptions::default()
+        };
+
+        let warnings = hex2bin_async(&hex[..], Vec::new(), &opts).await.unwrap();
+
+        let expected = crate::hex2bin_slice(hex, Vec::new(), &opts).unwrap();
+        assert!(warnings.skipped_lines > 0);
+        assert_eq!(warnings, expected);
+    }
+}
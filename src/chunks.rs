@@ -0,0 +1,250 @@
+use core::cmp;
+use core::iter;
+#[cfg(feature = "std")]
+use std::io::{Read, Result};
+
+use crate::record::Record;
+#[cfg(feature = "std")]
+use crate::record::DataBytes;
+#[cfg(feature = "std")]
+use crate::records::Records;
+
+/// Tracks the address base set by extended address records and resolves data
+/// record offsets to absolute addresses.
+///
+/// As in the Intel HEX specification, the offset of a data record wraps
+/// around at 16 bits after an ExtendedSegmentAddr record: bytes past offset
+/// 0xFFFF continue at the start of the same segment, not in the next one.
+/// After an ExtendedLinearAddr record, or without any, they continue at the
+/// next address and only wrap around at the end of the 32-bit address space.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AddressResolver {
+    base: u32,
+    /// The base was set by an ExtendedSegmentAddr record
+    segment: bool,
+}
+
+impl AddressResolver {
+    pub fn new() -> AddressResolver {
+        AddressResolver::default()
+    }
+
+    /// Absolute address of offset 0 in the current segment
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Updates the base on ExtendedLinearAddr and ExtendedSegmentAddr records,
+    /// returns true if the record was one of those
+    pub fn apply(&mut self, record: &Record) -> bool {
+        match *record {
+            Record::ExtendedLinearAddr(upper) => {
+                self.base = (upper as u32) << 16;
+                self.segment = false;
+            }
+            Record::ExtendedSegmentAddr(segment) => {
+                self.base = (segment as u32) << 4;
+                self.segment = true;
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Resolves the bytes of a data record at `offset` into one, or when the
+    /// offset wraps around, two pieces of (absolute address, bytes)
+    pub fn resolve<'a>(&self, offset: u16, bytes: &'a [u8]) -> impl Iterator<Item = (u32, &'a [u8])> {
+        let addr = self.base as u64 + offset as u64;
+        let (room, wrap_addr) = match self.segment {
+            true => (0x1_0000 - offset as u64, self.base),
+            false => ((1 << 32) - addr, 0),
+        };
+        let first_len = cmp::min(bytes.len() as u64, room) as usize;
+        let (head, tail) = bytes.split_at(first_len);
+
+        iter::once((addr as u32, head)).chain(Some((wrap_addr, tail)).filter(|(_, tail)| !tail.is_empty()))
+    }
+}
+
+/// Yields the data of an Intel HEX file as (absolute address, bytes) chunks.
+///
+/// Contiguous data records are merged into chunks of at most `chunk_size`
+/// bytes, a new chunk starts wherever the address jumps.
+#[cfg(feature = "std")]
+pub struct DataChunks<R: Read> {
+    records: Records<R>,
+    resolver: AddressResolver,
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    chunk_addr: u32,
+    /// Data record being merged into chunks
+    record: DataBytes,
+    /// Pieces of `record` not yet merged, as (address, start, end) in
+    /// `record`, up to two when the record wraps around
+    pieces: [(u32, usize, usize); 2],
+    num_pieces: usize,
+    piece_idx: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> DataChunks<R> {
+    pub fn new(reader: R, chunk_size: usize) -> DataChunks<R> {
+        assert!(chunk_size > 0, "chunk size must be at least one byte");
+
+        DataChunks {
+            records: Records::new(reader),
+            resolver: AddressResolver::new(),
+            chunk_size,
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_addr: 0,
+            record: DataBytes::new(&[]),
+            pieces: [(0, 0, 0); 2],
+            num_pieces: 0,
+            piece_idx: 0,
+        }
+    }
+
+    fn set_record(&mut self, offset: u16, bytes: DataBytes) {
+        self.record = bytes;
+        self.num_pieces = 0;
+        self.piece_idx = 0;
+
+        let mut start = 0;
+        for (addr, piece) in self.resolver.resolve(offset, &bytes) {
+            self.pieces[self.num_pieces] = (addr, start, start + piece.len());
+            self.num_pieces += 1;
+            start += piece.len();
+        }
+    }
+
+    /// Returns true if the chunk is done before all pending pieces are merged
+    fn merge_pieces(&mut self) -> bool {
+        while self.piece_idx < self.num_pieces {
+            let (addr, start, end) = self.pieces[self.piece_idx];
+
+            if self.chunk.is_empty() {
+                self.chunk_addr = addr;
+            } else if self.chunk.len() == self.chunk_size
+                || self.chunk_addr as u64 + self.chunk.len() as u64 != addr as u64
+            {
+                return true;
+            }
+
+            let take = cmp::min(end - start, self.chunk_size - self.chunk.len());
+            self.chunk.extend_from_slice(&self.record[start..start + take]);
+
+            if start + take == end {
+                self.piece_idx += 1;
+            } else {
+                self.pieces[self.piece_idx] = (addr + take as u32, start + take, end);
+            }
+        }
+
+        false
+    }
+
+    /// Returns the next chunk, or `None` after the EndOfFile record
+    pub fn next_chunk(&mut self) -> Option<Result<(u32, &[u8])>> {
+        self.chunk.clear();
+
+        loop {
+            if self.merge_pieces() {
+                break;
+            }
+
+            match self.records.next() {
+                None => break,
+                Some(Err(e)) => return Some(Err(e)),
+                Some(Ok((_, Record::Data { addr, bytes }))) => self.set_record(addr, bytes),
+                Some(Ok((_, record))) => {
+                    self.resolver.apply(&record);
+                }
+            }
+        }
+
+        if self.chunk.is_empty() {
+            None
+        } else {
+            Some(Ok((self.chunk_addr, &self.chunk)))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::chunks::*;
+
+    fn collect_chunks(hex: &[u8], chunk_size: usize) -> Vec<(u32, Vec<u8>)> {
+        let mut chunks = DataChunks::new(hex, chunk_size);
+        let mut out = Vec::new();
+
+        while let Some(chunk) = chunks.next_chunk() {
+            let (addr, bytes) = chunk.expect("to parse the hex");
+            out.push((addr, bytes.to_vec()));
+        }
+
+        out
+    }
+
+    #[test]
+    fn it_merges_contiguous_records() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let bin = include_bytes!("../test/bin/sniffer_nrf52840dk_nrf52840_7cc811f.truth");
+
+        let chunks = collect_chunks(hex, 4096);
+
+        assert_eq!(chunks.len(), bin.len().div_ceil(4096));
+        for (i, (addr, bytes)) in chunks.iter().enumerate() {
+            assert_eq!(*addr as usize, i * 4096);
+            assert_eq!(&bytes[..], &bin[i * 4096..cmp::min(bin.len(), (i + 1) * 4096)]);
+        }
+    }
+
+    #[test]
+    fn it_starts_a_new_chunk_at_address_gaps() {
+        let hex = include_bytes!("../test/addrspace-gap-mid.in");
+
+        let chunks = collect_chunks(hex, 1024);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].0, chunks[0].1.len()), (0x0000, 0x90));
+        assert_eq!((chunks[1].0, chunks[1].1.len()), (0xF100, 0xE0));
+    }
+
+    #[test]
+    fn it_applies_extended_addresses() {
+        let hex = b":020000021200EA\n:0400100001020304E2\n:020000040800F2\n:02000000AABB99\n:00000001FF\n";
+
+        let chunks = collect_chunks(hex, 16);
+
+        assert_eq!(chunks, vec![(0x12010, vec![1, 2, 3, 4]), (0x0800_0000, vec![0xAA, 0xBB])]);
+    }
+
+    #[test]
+    fn it_wraps_offsets_within_a_segment() {
+        let hex = b":020000021000EC\n:04FFFE0001020304F5\n:00000001FF\n";
+
+        let chunks = collect_chunks(hex, 16);
+
+        assert_eq!(chunks, vec![(0x1FFFE, vec![1, 2]), (0x10000, vec![3, 4])]);
+    }
+
+    #[test]
+    fn it_continues_linear_offsets_past_0xffff() {
+        let hex = b":020000040000FA\n:02FFFF00AABB9B\n:020000040001F9\n:04FFFE0001020304F5\n:00000001FF\n";
+
+        let chunks = collect_chunks(hex, 16);
+
+        assert_eq!(chunks, vec![(0xFFFF, vec![0xAA, 0xBB]), (0x1FFFE, vec![1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn it_wraps_linear_offsets_at_the_end_of_the_address_space() {
+        let hex = b":02000004FFFFFC\n:02FFFF00AABB9B\n:00000001FF\n";
+
+        let chunks = collect_chunks(hex, 16);
+
+        assert_eq!(chunks, vec![(0xFFFF_FFFF, vec![0xAA]), (0, vec![0xBB])]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Result, Write};
+
+const RESET: &str = "\x1b[0m";
+
+/// SGR colour of a byte, by byte class
+fn class_color(b: u8) -> &'static str {
+    match b {
+        0x00 => "90",
+        0xff => "34",
+        b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' => "32",
+        0x21..=0x7e => "36",
+        _ => "33",
+    }
+}
+
+fn write_styled<W: Write>(writer: &mut W, b: u8, highlight: bool, text: fmt::Arguments) -> Result<()> {
+    let invert = if highlight { ";7" } else { "" };
+    write!(writer, "\x1b[{}{}m{}{}", class_color(b), invert, text, RESET)
+}
+
+/// Colours dump rows by byte class and highlights a byte pattern or address
+/// ranges.
+///
+/// A pattern match may span several rows, so rows are queued until no match
+/// in progress can reach back into them.
+pub struct Colors {
+    pattern: Vec<u8>,
+    /// Sorted [start, end) ranges to highlight
+    ranges: Vec<(u64, u64)>,
+    /// The last bytes seen as (address, byte), at most `pattern.len()` of them
+    window: VecDeque<(u64, u8)>,
+    /// Pattern matches as [start, end) address ranges
+    matches: VecDeque<(u64, u64)>,
+    /// Rows waiting to be printed, `None` is a squeezed `*` line
+    queue: VecDeque<Option<(u32, Vec<u8>)>>,
+}
+
+impl Colors {
+    pub fn new(pattern: Vec<u8>, ranges: Vec<(u64, u64)>) -> Colors {
+        Colors {
+            pattern,
+            ranges,
+            window: VecDeque::new(),
+            matches: VecDeque::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Looks for the pattern in a row of data
+    pub fn scan(&mut self, addr: u32, bytes: &[u8]) {
+        if self.pattern.is_empty() {
+            return;
+        }
+
+        for (i, &b) in bytes.iter().enumerate() {
+            let byte_addr = addr as u64 + i as u64;
+
+            if self.window.back().is_some_and(|(a, _)| a + 1 != byte_addr) {
+                self.window.clear();
+            }
+            if self.window.len() == self.pattern.len() {
+                self.window.pop_front();
+            }
+            self.window.push_back((byte_addr, b));
+
+            if self.window.len() == self.pattern.len()
+                && self.window.iter().map(|(_, b)| b).eq(self.pattern.iter())
+            {
+                self.matches.push_back((self.window[0].0, byte_addr + 1));
+            }
+        }
+    }
+
+    /// Forgets partial matches, data that follows is not contiguous
+    pub fn end_segment(&mut self) {
+        self.window.clear();
+    }
+
+    /// Queues a row of data, or a `*` line if `None`
+    pub fn queue(&mut self, row: Option<(u32, &[u8])>) {
+        self.queue.push_back(row.map(|(addr, bytes)| (addr, bytes.to_vec())));
+    }
+
+    /// Prints queued rows that no partial match can reach into anymore, or
+    /// all of them if `all` is set
+    pub fn flush<W: Write>(&mut self, writer: &mut W, all: bool) -> Result<()> {
+        let safe_addr = if all {
+            None
+        } else {
+            self.window.front().map(|(addr, _)| *addr)
+        };
+
+        while let Some(front) = self.queue.front() {
+            match front {
+                Some((addr, bytes)) => {
+                    if safe_addr.is_some_and(|safe| *addr as u64 + bytes.len() as u64 > safe) {
+                        break;
+                    }
+                    self.write_row(writer, *addr, bytes)?;
+
+                    let row_end = *addr as u64 + bytes.len() as u64;
+                    while self.matches.front().is_some_and(|(_, end)| *end <= row_end) {
+                        self.matches.pop_front();
+                    }
+                }
+                None => writeln!(writer, "*")?,
+            }
+            self.queue.pop_front();
+        }
+
+        Ok(())
+    }
+
+    fn is_highlighted(&self, addr: u64) -> bool {
+        self.ranges.iter().any(|(start, end)| (*start..*end).contains(&addr))
+            || self.matches.iter().any(|(start, end)| (*start..*end).contains(&addr))
+    }
+
+    /// Same layout as the plain dump rows, with every byte coloured
+    fn write_row<W: Write>(&self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        write!(writer, "{:#010X}  ", addr)?;
+
+        let mut hex_width = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            write_styled(writer, b, self.is_highlighted(addr as u64 + i as u64), format_args!("{:02X}", b))?;
+            hex_width += 2;
+
+            if i < 15 {
+                let sep: &[u8] = if i == 7 { b"  " } else { b" " };
+                writer.write_all(sep)?;
+                hex_width += sep.len();
+            }
+        }
+        write!(writer, "{:1$}  |", "", 48 - hex_width)?;
+
+        for (i, &b) in bytes.iter().enumerate() {
+            let c = if (32..127).contains(&b) { b as char } else { '.' };
+            write_styled(writer, b, self.is_highlighted(addr as u64 + i as u64), format_args!("{}", c))?;
+        }
+        writeln!(writer, "{:1$}|", "", 16 - bytes.len())
+    }
+}
@@ -1,21 +1,132 @@
+use std::cmp;
+use std::convert::TryInto;
 use std::io::{Result, Write};
 
-use crate::helpers::*;
+use crate::color::Colors;
 
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Execution start address carried by a StartSegmentAddr or StartLinearAddr record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartAddr {
+    Segment { cs: u16, ip: u16 },
+    Linear(u32),
+}
+
+/// Sink for the contents of an Intel HEX file, see `convert`.
+///
+/// Calls arrive in file order:
+///
+/// - `begin_segment` before the first `write` of a contiguous run of data,
+///   and `end_segment` after its last one. A segment ends at an address
+///   discontinuity and before every extended address record, so two
+///   segments may still be adjacent in the address space.
+/// - `write` with the decoded data bytes of at most one 16 byte row, at
+///   their absolute address, that is with extended segment and linear
+///   addresses already applied. Rows never span two segments.
+/// - `start_addr` whenever a start address record is read.
+/// - `finish` once, after the EndOfFile record.
+///
+/// `writer` is the output passed to `convert`, a sink that only collects
+/// data can ignore it.
 pub trait DataWriter<W: Write> {
-    fn write(&mut self, writer: &mut W, addr: i64, buf: &[u8]) -> Result<()>;
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()>;
+
+    fn begin_segment(&mut self, _writer: &mut W, _addr: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_segment(&mut self, _writer: &mut W) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_addr(&mut self, _writer: &mut W, _start_addr: StartAddr) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _writer: &mut W) -> Result<()> {
+        Ok(())
+    }
 }
 
-pub struct HexDataWriter {}
+impl<W: Write, DW: DataWriter<W> + ?Sized> DataWriter<W> for &mut DW {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        (**self).write(writer, addr, bytes)
+    }
+
+    fn begin_segment(&mut self, writer: &mut W, addr: u32) -> Result<()> {
+        (**self).begin_segment(writer, addr)
+    }
+
+    fn end_segment(&mut self, writer: &mut W) -> Result<()> {
+        (**self).end_segment(writer)
+    }
+
+    fn start_addr(&mut self, writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        (**self).start_addr(writer, start_addr)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        (**self).finish(writer)
+    }
+}
+
+impl<W: Write, DW: DataWriter<W> + ?Sized> DataWriter<W> for Box<DW> {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        (**self).write(writer, addr, bytes)
+    }
+
+    fn begin_segment(&mut self, writer: &mut W, addr: u32) -> Result<()> {
+        (**self).begin_segment(writer, addr)
+    }
+
+    fn end_segment(&mut self, writer: &mut W) -> Result<()> {
+        (**self).end_segment(writer)
+    }
+
+    fn start_addr(&mut self, writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        (**self).start_addr(writer, start_addr)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        (**self).finish(writer)
+    }
+}
+
+pub struct HexDataWriter {
+    /// Collapse runs of identical rows into a single `*` line
+    squeeze: bool,
+    prev_row: Option<[u8; 16]>,
+    /// Address of the last row left out while squeezing
+    squeezed_addr: Option<u32>,
+    colors: Option<Colors>,
+}
 
 impl HexDataWriter {
-    pub fn new() -> HexDataWriter {
-        HexDataWriter {}
+    pub fn new(squeeze: bool, colors: Option<Colors>) -> HexDataWriter {
+        HexDataWriter {
+            squeeze,
+            prev_row: None,
+            squeezed_addr: None,
+            colors,
+        }
+    }
+
+    /// Prints a row, or a `*` line if `None`
+    fn emit<W: Write>(&mut self, writer: &mut W, row: Option<(u32, &[u8])>) -> Result<()> {
+        match (&mut self.colors, row) {
+            (Some(colors), row) => {
+                colors.queue(row);
+                Ok(())
+            }
+            (None, Some((addr, bytes))) => HexDataWriter::print_row(writer, addr, bytes),
+            (None, None) => writeln!(writer, "*"),
+        }
     }
 
     fn write_row<W: Write>(
         mut writer: W,
-        addr: i64,
+        addr: u32,
         hex_buf: &[u8],
         hex_len: usize,
         str_buf: &[u8],
@@ -29,23 +140,20 @@ impl HexDataWriter {
             unsafe { std::str::from_utf8_unchecked(&str_buf[..str_len]) }
         )
     }
-}
 
-impl<W: Write> DataWriter<W> for HexDataWriter {
-    fn write(&mut self, writer: &mut W, addr: i64, buf: &[u8]) -> Result<()> {
+    fn print_row<W: Write>(writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
         let mut hex_buf = [0u8; 64];
         let mut hex_len = 0;
 
-        for i in 0..buf.len() {
-            hex_buf[hex_len] = buf[i];
-            hex_len += 1;
+        for (i, &b) in bytes.iter().enumerate() {
+            hex_buf[hex_len] = HEX_DIGITS[(b >> 4) as usize];
+            hex_buf[hex_len + 1] = HEX_DIGITS[(b & 0xf) as usize];
+            hex_len += 2;
 
-            if i < 31 {
-                if (i + 1) % 16 == 0 {
-                    hex_buf[hex_len] = b' ';
-                    hex_len += 1;
-                }
-                if (i + 1) % 2 == 0 {
+            if i < 15 {
+                hex_buf[hex_len] = b' ';
+                hex_len += 1;
+                if i == 7 {
                     hex_buf[hex_len] = b' ';
                     hex_len += 1;
                 }
@@ -53,66 +161,524 @@ impl<W: Write> DataWriter<W> for HexDataWriter {
         }
 
         let mut str_buf = [0u8; 16];
-        let mut str_len = 0;
-        for (i, bs) in buf.chunks(2).enumerate() {
-            str_buf[i] = hex_to_u8(&bs[..2]);
-            if str_buf[i] >= 127 || str_buf[i] <= 31 {
-                str_buf[i] = b'.';
+        for (i, &b) in bytes.iter().enumerate() {
+            str_buf[i] = if (32..127).contains(&b) { b } else { b'.' };
+        }
+
+        HexDataWriter::write_row(writer, addr, &hex_buf, hex_len, &str_buf, bytes.len())
+    }
+}
+
+impl<W: Write> DataWriter<W> for HexDataWriter {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        if let Some(colors) = &mut self.colors {
+            colors.scan(addr, bytes);
+        }
+
+        if !self.squeeze {
+            self.emit(writer, Some((addr, bytes)))?;
+        } else if self.prev_row.is_some_and(|prev| prev[..] == *bytes) {
+            if self.squeezed_addr.is_none() {
+                self.emit(writer, None)?;
+            }
+            self.squeezed_addr = Some(addr);
+        } else {
+            /* Only full rows take part in squeezing */
+            self.prev_row = bytes.try_into().ok();
+            self.squeezed_addr = None;
+
+            self.emit(writer, Some((addr, bytes)))?;
+        }
+
+        match &mut self.colors {
+            Some(colors) => colors.flush(writer, false),
+            None => Ok(()),
+        }
+    }
+
+    /// A squeezed run never continues into the next segment, its last row is
+    /// printed so that the end of the run is visible
+    fn end_segment(&mut self, writer: &mut W) -> Result<()> {
+        if let (Some(addr), Some(row)) = (self.squeezed_addr.take(), self.prev_row) {
+            self.emit(writer, Some((addr, &row)))?;
+        }
+        self.prev_row = None;
+
+        match &mut self.colors {
+            Some(colors) => {
+                colors.end_segment();
+                colors.flush(writer, true)
             }
-            str_len += 1;
+            None => Ok(()),
+        }
+    }
+}
+
+/// Wraps a dump writer and separates contiguous regions of data with
+/// `-- segment` and `-- gap` lines, followed by a summary footer.
+///
+/// The segment line heads the rows of its segment and carries its length,
+/// so the rows of a segment are held back until the segment ends.
+pub struct AnnotatedDataWriter<DW: DataWriter<Vec<u8>>> {
+    inner: DW,
+    /// Rows of the current segment
+    rows: Vec<u8>,
+    /// Current segment as (start address, length)
+    segment: Option<(u32, u64)>,
+    total_bytes: u64,
+    segments: usize,
+}
+
+impl<DW: DataWriter<Vec<u8>>> AnnotatedDataWriter<DW> {
+    pub fn new(inner: DW) -> AnnotatedDataWriter<DW> {
+        AnnotatedDataWriter {
+            inner,
+            rows: Vec::new(),
+            segment: None,
+            total_bytes: 0,
+            segments: 0,
+        }
+    }
+
+    fn write_segment<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        if let Some((start, len)) = self.segment {
+            writeln!(writer, "-- segment {:#010X} len {:#X} --", start, len)?;
+        }
+        writer.write_all(&self.rows)?;
+        self.rows.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write, DW: DataWriter<Vec<u8>>> DataWriter<W> for AnnotatedDataWriter<DW> {
+    fn write(&mut self, _writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        if let Some((_, len)) = &mut self.segment {
+            *len += bytes.len() as u64;
         }
+        self.total_bytes += bytes.len() as u64;
+        self.inner.write(&mut self.rows, addr, bytes)
+    }
+
+    /// Segments of the data writer that continue the previous one, split by
+    /// an extended address record, are annotated as one
+    fn begin_segment(&mut self, writer: &mut W, addr: u32) -> Result<()> {
+        match self.segment {
+            Some((start, len)) if start as u64 + len == addr as u64 => {}
+            prev => {
+                self.write_segment(writer)?;
+
+                if let Some((start, len)) = prev {
+                    let end = start as u64 + len;
+                    if addr as u64 > end {
+                        writeln!(
+                            writer,
+                            "-- gap {:#010X}..{:#010X} ({} bytes) --",
+                            end,
+                            addr,
+                            addr as u64 - end
+                        )?;
+                    }
+                }
+
+                self.segment = Some((addr, 0));
+                self.segments += 1;
+            }
+        }
+
+        self.inner.begin_segment(&mut self.rows, addr)
+    }
+
+    fn end_segment(&mut self, _writer: &mut W) -> Result<()> {
+        self.inner.end_segment(&mut self.rows)
+    }
+
+    fn start_addr(&mut self, _writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        self.inner.start_addr(&mut self.rows, start_addr)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        self.inner.finish(&mut self.rows)?;
+        self.write_segment(writer)?;
+
+        writeln!(
+            writer,
+            "-- total {} bytes in {} segments --",
+            self.total_bytes, self.segments
+        )
+    }
+}
+
+/// Wraps a writer and only passes on data within the given address ranges,
+/// clipping rows that cross a range boundary.
+pub struct RangeDataWriter<DW> {
+    inner: DW,
+    /// Sorted, non-overlapping [start, end) ranges
+    ranges: Vec<(u64, u64)>,
+    /// End of the last piece passed on, `None` if the inner segment is closed
+    next_addr: Option<u64>,
+}
+
+impl<DW> RangeDataWriter<DW> {
+    pub fn new(inner: DW, ranges: Vec<(u64, u64)>) -> RangeDataWriter<DW> {
+        RangeDataWriter {
+            inner,
+            ranges,
+            next_addr: None,
+        }
+    }
+}
 
-        HexDataWriter::write_row(writer, addr, &hex_buf, hex_len, &str_buf, str_len)?;
+impl<W: Write, DW: DataWriter<W>> DataWriter<W> for RangeDataWriter<DW> {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        let start_addr = addr as u64;
+        let end_addr = start_addr + bytes.len() as u64;
+
+        for &(start, end) in &self.ranges {
+            if end <= start_addr {
+                continue;
+            }
+            if start >= end_addr {
+                break;
+            }
+
+            let clip_start = cmp::max(start, start_addr);
+            let clip_end = cmp::min(end, end_addr);
+
+            /* Clipped pieces of one segment are not contiguous */
+            match self.next_addr {
+                Some(next) if next == clip_start => {}
+                Some(_) => {
+                    self.inner.end_segment(writer)?;
+                    self.inner.begin_segment(writer, clip_start as u32)?;
+                }
+                None => self.inner.begin_segment(writer, clip_start as u32)?,
+            }
+
+            let from = (clip_start - start_addr) as usize;
+            let to = (clip_end - start_addr) as usize;
+            self.inner.write(writer, clip_start as u32, &bytes[from..to])?;
+            self.next_addr = Some(clip_end);
+        }
 
         Ok(())
     }
+
+    fn end_segment(&mut self, writer: &mut W) -> Result<()> {
+        match self.next_addr.take() {
+            Some(_) => self.inner.end_segment(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn start_addr(&mut self, writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        self.inner.start_addr(writer, start_addr)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        self.inner.finish(writer)
+    }
 }
 
 pub struct BinDataWriter {
-    prev_addr: i64,
-    prev_bytes_written: i64,
+    /// Address following the last byte written, `None` before the first write
+    next_addr: Option<u64>,
     fill_byte: u8,
 }
 
 impl BinDataWriter {
     pub fn new(fill_byte: u8) -> BinDataWriter {
         BinDataWriter {
-            prev_addr: 0,
-            prev_bytes_written: 0,
+            next_addr: None,
             fill_byte,
         }
     }
+
+    /// Continues the binary at `addr`, as if the last data ended there
+    pub(crate) fn seek(&mut self, addr: u64) {
+        self.next_addr = Some(addr);
+    }
+
+    /// Fills from the end of the last data up to `addr`
+    pub(crate) fn fill<W: Write>(&mut self, writer: &mut W, addr: u64) -> Result<()> {
+        // Only fill between addresses, not from 0 up to start address
+        if let Some(next_addr) = self.next_addr {
+            if addr < next_addr {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Expected increasing address order; found {:#010x} followed by {:#010x}", next_addr, addr),
+                ));
+            }
+
+            let fill = [self.fill_byte; 16];
+            let mut fill_bytes_to_write = addr - next_addr;
+            while fill_bytes_to_write > 0 {
+                let n = cmp::min(fill_bytes_to_write, fill.len() as u64) as usize;
+                writer.write_all(&fill[..n])?;
+                fill_bytes_to_write -= n as u64;
+            }
+            self.next_addr = Some(addr);
+        }
+
+        Ok(())
+    }
 }
 
 impl<W: Write> DataWriter<W> for BinDataWriter {
-    fn write(&mut self, writer: &mut W, addr: i64, buf: &[u8]) -> Result<()> {
-        let mut byte_buf = [0u8; 16];
-        let mut byte_buf_len = 0;
-        let fill_bytes_to_write = addr - self.prev_addr - self.prev_bytes_written;
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        self.fill(writer, addr as u64)?;
+        writer.write_all(bytes)?;
+        self.next_addr = Some(addr as u64 + bytes.len() as u64);
 
-        if addr < self.prev_addr {
-            eprintln!("Expected increasing address order; found {:#010x} followed by {:#010x}",
-                self.prev_addr, addr);
-            return Err(std::io::ErrorKind::Unsupported.into());
+        Ok(())
+    }
+}
+
+/// Writes the data back out as Intel HEX: data records of up to 16 bytes,
+/// an ExtendedLinearAddr record wherever the upper 16 address bits change
+/// and the start address just before the EndOfFile record.
+#[derive(Default)]
+pub struct IntelHexDataWriter {
+    /// Upper 16 bits of the address of the last data record
+    upper: u16,
+    start_addr: Option<StartAddr>,
+}
+
+impl IntelHexDataWriter {
+    pub fn new() -> IntelHexDataWriter {
+        IntelHexDataWriter::default()
+    }
+
+    fn write_record<W: Write>(writer: &mut W, record_type: u8, addr: u16, data: &[u8]) -> Result<()> {
+        let header = [data.len() as u8, (addr >> 8) as u8, addr as u8, record_type];
+        let mut line = Vec::with_capacity(1 + 2 * (header.len() + data.len() + 1) + 1);
+        let mut sum = 0u8;
+
+        line.push(b':');
+        for &b in header.iter().chain(data) {
+            line.push(HEX_DIGITS[(b >> 4) as usize]);
+            line.push(HEX_DIGITS[(b & 0xf) as usize]);
+            sum = sum.wrapping_add(b);
         }
+        let checksum = sum.wrapping_neg();
+        line.push(HEX_DIGITS[(checksum >> 4) as usize]);
+        line.push(HEX_DIGITS[(checksum & 0xf) as usize]);
+        line.push(b'\n');
 
-        // Only fill between addresses, not from 0 up to start address
-        if self.prev_addr != 0 {
-            for _ in 0..fill_bytes_to_write {
-                writer.write(&[ self.fill_byte ])?;
+        writer.write_all(&line)
+    }
+}
+
+impl<W: Write> DataWriter<W> for IntelHexDataWriter {
+    fn write(&mut self, writer: &mut W, mut addr: u32, mut bytes: &[u8]) -> Result<()> {
+        while !bytes.is_empty() {
+            let upper = (addr >> 16) as u16;
+            if upper != self.upper {
+                IntelHexDataWriter::write_record(writer, 0x04, 0, &upper.to_be_bytes())?;
+                self.upper = upper;
+            }
+
+            /* Records neither exceed 16 bytes nor cross a 64 KiB boundary */
+            let n = cmp::min(cmp::min(bytes.len(), 16), 0x10000 - (addr & 0xffff) as usize);
+            IntelHexDataWriter::write_record(writer, 0x00, addr as u16, &bytes[..n])?;
+            addr = addr.wrapping_add(n as u32);
+            bytes = &bytes[n..];
+        }
+
+        Ok(())
+    }
+
+    fn start_addr(&mut self, _writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        self.start_addr = Some(start_addr);
+        Ok(())
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        match self.start_addr {
+            Some(StartAddr::Segment { cs, ip }) => {
+                let data = [(cs >> 8) as u8, cs as u8, (ip >> 8) as u8, ip as u8];
+                IntelHexDataWriter::write_record(writer, 0x03, 0, &data)?
+            }
+            Some(StartAddr::Linear(eip)) => {
+                IntelHexDataWriter::write_record(writer, 0x05, 0, &eip.to_be_bytes())?
+            }
+            None => {}
+        }
+
+        IntelHexDataWriter::write_record(writer, 0x01, 0, &[])
+    }
+}
+
+/// Reproduces hex2dump.py of python-intelhex.
+///
+/// Python sorts all data before dumping, prints the start address first and
+/// derives the address width from the highest address, so rows can only be
+/// written once the whole file has been read.
+pub struct PyHexDataWriter {
+    start_addr: Option<StartAddr>,
+    /// Contiguous runs of data as (start address, bytes)
+    runs: Vec<(u64, Vec<u8>)>,
+}
+
+impl PyHexDataWriter {
+    pub fn new() -> PyHexDataWriter {
+        PyHexDataWriter {
+            start_addr: None,
+            runs: Vec::new(),
+        }
+    }
+
+    fn write_header<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self.start_addr {
+            Some(StartAddr::Linear(eip)) => writeln!(writer, "EIP = {:#010X}", eip),
+            Some(StartAddr::Segment { cs, ip }) => {
+                writeln!(writer, "CS = {:#06X}, IP = {:#06X}", cs, ip)
             }
+            None => Ok(()),
         }
+    }
+}
 
-        for (i, bs) in buf.chunks(2).enumerate() {
-            byte_buf[i] = hex_to_u8(&bs[..2]);
-            byte_buf_len += 1;
+impl<W: Write> DataWriter<W> for PyHexDataWriter {
+    fn write(&mut self, _writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        let addr = addr as u64;
+
+        match self.runs.last_mut() {
+            Some((start, data)) if *start + data.len() as u64 == addr => data.extend_from_slice(bytes),
+            _ => self.runs.push((addr, bytes.to_vec())),
         }
 
-        writer.write(&byte_buf[..byte_buf_len])?;
+        Ok(())
+    }
+
+    fn start_addr(&mut self, _writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        self.start_addr = Some(start_addr);
+        Ok(())
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        self.write_header(writer)?;
+
+        self.runs.sort_by_key(|(start, _)| *start);
+
+        for pair in self.runs.windows(2) {
+            let (prev_start, prev_data) = &pair[0];
+            if prev_start + prev_data.len() as u64 > pair[1].0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Data overlapped at address {:#010x}", pair[1].0),
+                ));
+            }
+        }
+
+        let (min_addr, max_addr) = match (self.runs.first(), self.runs.last()) {
+            (Some((first, _)), Some((last, data))) => (*first, last + data.len() as u64 - 1),
+            _ => return Ok(()),
+        };
+
+        let start_addr = (min_addr / 16) * 16;
+        let end_addr = (max_addr / 16 + 1) * 16;
+        let digits = cmp::max(format!("{:X}", end_addr).len(), 4);
+
+        let mut run_idx = 0;
+        let mut str_buf = [0u8; 16];
+
+        for row_addr in (start_addr..end_addr).step_by(16) {
+            write!(writer, "{:0width$X} ", row_addr, width = digits)?;
 
-        self.prev_addr = addr;
-        self.prev_bytes_written = byte_buf_len as i64;
+            for i in 0..16 {
+                let addr = row_addr + i as u64;
+
+                while run_idx < self.runs.len()
+                    && self.runs[run_idx].0 + self.runs[run_idx].1.len() as u64 <= addr
+                {
+                    run_idx += 1;
+                }
+
+                let byte = self
+                    .runs
+                    .get(run_idx)
+                    .filter(|(start, _)| *start <= addr)
+                    .map(|(start, data)| data[(addr - start) as usize]);
+
+                match byte {
+                    Some(b) => {
+                        write!(writer, " {:02X}", b)?;
+                        str_buf[i] = if (32..127).contains(&b) { b } else { b'.' };
+                    }
+                    None => {
+                        writer.write_all(b" --")?;
+                        str_buf[i] = b' ';
+                    }
+                }
+            }
+
+            writer.write_all(b"  |")?;
+            writer.write_all(&str_buf)?;
+            writer.write_all(b"|\n")?;
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::datawriter::*;
+    use crate::pipeline::{hex2bin, hex2dump_slice, DumpOptions};
+
+    #[test]
+    fn it_writes_intel_hex_that_converts_back_to_the_same_binary() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let mut output = Vec::new();
+
+        crate::convert_slice(hex, &mut output, &mut IntelHexDataWriter::new()).unwrap();
+
+        let (mut bin, mut truth) = (Vec::new(), Vec::new());
+        hex2bin(&output[..], &mut bin, 0xff).unwrap();
+        hex2bin(&hex[..], &mut truth, 0xff).unwrap();
+        assert_eq!(bin, truth);
+        assert!(output.ends_with(b":0400000300003605BE\n:00000001FF\n"));
+    }
+
+    #[test]
+    fn it_writes_extended_linear_addresses() {
+        let hex = b":020000020FFFEE\n:10000800000102030405060708090A0B0C0D0E0F70\n:0400000508000131BD\n:00000001FF\n";
+        let mut output = Vec::new();
+
+        crate::convert_slice(&hex[..], &mut output, &mut IntelHexDataWriter::new()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ":08FFF8000001020304050607E5\n:020000040001F9\n:0800000008090A0B0C0D0E0F9C\n:0400000508000131BD\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn it_refuses_decreasing_addresses_in_a_binary() {
+        let hex = b":0100100001EE\n:0100000002FD\n:00000001FF\n";
+
+        let err = hex2bin(&hex[..], Vec::new(), 0xff).unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Expected increasing address order; found 0x00000011 followed by 0x00000000");
+    }
+
+    #[test]
+    fn it_heads_each_segment_with_its_line() {
+        let hex = b":0400000001020304F2\n:020010000506E3\n:00000001FF\n";
+        let mut output = Vec::new();
+
+        hex2dump_slice(&hex[..], &mut output, &DumpOptions { annotate: true, ..DumpOptions::default() }).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "-- segment 0x00000000 len 0x4 --\n\
+             0x00000000  01 02 03 04                                       |....            |\n\
+             -- gap 0x00000004..0x00000010 (12 bytes) --\n\
+             -- segment 0x00000010 len 0x2 --\n\
+             0x00000010  05 06                                             |..              |\n\
+             -- total 6 bytes in 2 segments --\n"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::io::{self, Read, Result, Write};
+use std::str::FromStr;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::datawriter::{BinDataWriter, DataWriter, RangeDataWriter};
+use crate::pipeline::{convert_records, BinOptions, ConvertWarnings, RecordSource};
+use crate::record::SliceRecords;
+use crate::records::Records;
+
+/// Parameters of a CRC as listed in the catalogue of parametrised CRC
+/// algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcParams {
+    /// Width in bits, 1 to 32
+    pub width: u8,
+    /// Generator polynomial in normal form, without its top bit
+    pub poly: u32,
+    /// Register value before the first byte
+    pub init: u32,
+    /// Feed bytes least significant bit first and reflect the result
+    pub reflect: bool,
+    /// Xored into the final register value
+    pub xor_out: u32,
+}
+
+impl CrcParams {
+    /// CRC-16/CCITT-FALSE, check value 0x29b1
+    pub const CRC16_CCITT: CrcParams = CrcParams { width: 16, poly: 0x1021, init: 0xffff, reflect: false, xor_out: 0 };
+
+    /// CRC-32 of zlib and Ethernet, check value 0xcbf43926
+    pub const CRC32: CrcParams =
+        CrcParams { width: 32, poly: 0x04c1_1db7, init: 0xffff_ffff, reflect: true, xor_out: 0xffff_ffff };
+
+    fn mask(&self) -> u32 {
+        u32::MAX >> (32 - self.width)
+    }
+}
+
+/// Table driven CRC of any width up to 32 bits
+#[derive(Debug, Clone)]
+pub struct Crc {
+    params: CrcParams,
+    table: Box<[u32; 256]>,
+    /// The register reflected for reflected CRCs, otherwise shifted up to
+    /// the top bits
+    reg: u32,
+}
+
+impl Crc {
+    /// Fails if the width of `params` is not 1 to 32 bits
+    pub fn new(params: CrcParams) -> Result<Crc> {
+        if !(1..=32).contains(&params.width) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("CRC width must be 1 to 32 bits, found {}", params.width),
+            ));
+        }
+        let mask = params.mask();
+        let shift = 32 - params.width as u32;
+
+        let mut table = Box::new([0u32; 256]);
+        let reg = if params.reflect {
+            let poly = (params.poly & mask).reverse_bits() >> shift;
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut crc = i as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+                }
+                *entry = crc;
+            }
+            (params.init & mask).reverse_bits() >> shift
+        } else {
+            let poly = (params.poly & mask) << shift;
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut crc = (i as u32) << 24;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ poly } else { crc << 1 };
+                }
+                *entry = crc;
+            }
+            (params.init & mask) << shift
+        };
+
+        Ok(Crc { params, table, reg })
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        if self.params.reflect {
+            for &b in bytes {
+                self.reg = self.table[((self.reg ^ b as u32) & 0xff) as usize] ^ (self.reg >> 8);
+            }
+        } else {
+            for &b in bytes {
+                self.reg = self.table[((self.reg >> 24) ^ b as u32) as usize] ^ (self.reg << 8);
+            }
+        }
+    }
+
+    /// The CRC of the bytes fed so far
+    pub fn finish(&self) -> u32 {
+        let reg = if self.params.reflect { self.reg } else { self.reg >> (32 - self.params.width as u32) };
+        (reg ^ self.params.xor_out) & self.params.mask()
+    }
+}
+
+/// Checksum or digest computed by `hash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Crc(CrcParams),
+    Sha256,
+    Md5,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Algorithm, String> {
+        match s {
+            "crc16-ccitt" => Ok(Algorithm::Crc(CrcParams::CRC16_CCITT)),
+            "crc32" => Ok(Algorithm::Crc(CrcParams::CRC32)),
+            "sha256" => Ok(Algorithm::Sha256),
+            "md5" => Ok(Algorithm::Md5),
+            _ => Err(format!("Expected crc16-ccitt, crc32, sha256 or md5, found {:?}", s)),
+        }
+    }
+}
+
+/// Computes the digest of an `Algorithm` over the bytes written to it
+#[derive(Clone)]
+pub enum Hasher {
+    Crc(Crc),
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl Hasher {
+    /// Fails on CRC parameters `Crc::new` refuses
+    pub fn new(algorithm: Algorithm) -> Result<Hasher> {
+        Ok(match algorithm {
+            Algorithm::Crc(params) => Hasher::Crc(Crc::new(params)?),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+        })
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Crc(crc) => crc.update(bytes),
+            Hasher::Sha256(sha) => sha.update(bytes),
+            Hasher::Md5(md5) => md5.update(bytes),
+        }
+    }
+
+    /// The digest of the bytes fed so far, a CRC as its big-endian bytes
+    pub fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Crc(crc) => {
+                let len = (crc.params.width as usize).div_ceil(8);
+                crc.finish().to_be_bytes()[4 - len..].to_vec()
+            }
+            Hasher::Sha256(sha) => sha.finalize().to_vec(),
+            Hasher::Md5(md5) => md5.finalize().to_vec(),
+        }
+    }
+}
+
+impl fmt::Debug for Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hasher::Crc(crc) => f.debug_tuple("Crc").field(&crc.params).finish(),
+            Hasher::Sha256(_) => write!(f, "Sha256"),
+            Hasher::Md5(_) => write!(f, "Md5"),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Passes the data within sorted, non-overlapping ranges to a
+/// `BinDataWriter` and fills every range from its start to its end
+struct FilledRanges {
+    bin: BinDataWriter,
+    ranges: Vec<(u64, u64)>,
+    /// Index of the range being filled
+    current: usize,
+}
+
+impl FilledRanges {
+    fn new(fill_byte: u8, ranges: Vec<(u64, u64)>) -> FilledRanges {
+        let mut bin = BinDataWriter::new(fill_byte);
+        if let Some(&(start, _)) = ranges.first() {
+            bin.seek(start);
+        }
+        FilledRanges { bin, ranges, current: 0 }
+    }
+
+    /// Fills the current range up to its end and moves on to the next one
+    fn next_range<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        self.bin.fill(writer, self.ranges[self.current].1)?;
+        self.current += 1;
+        if let Some(&(start, _)) = self.ranges.get(self.current) {
+            self.bin.seek(start);
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> DataWriter<W> for FilledRanges {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        while self.ranges[self.current].1 <= addr as u64 {
+            self.next_range(writer)?;
+        }
+        self.bin.write(writer, addr, bytes)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        while self.current < self.ranges.len() {
+            self.next_range(writer)?;
+        }
+        Ok(())
+    }
+}
+
+fn hash_records<S: RecordSource>(
+    records: S,
+    algorithm: Algorithm,
+    opts: &BinOptions,
+) -> Result<(Vec<u8>, ConvertWarnings)> {
+    let mut hasher = Hasher::new(algorithm)?;
+
+    if opts.ranges.is_empty() {
+        let mut data_writer = BinDataWriter::new(opts.fill_byte);
+        let warnings = convert_records(records, &mut hasher, &mut data_writer, opts.checks())?;
+        return Ok((hasher.finish(), warnings));
+    }
+
+    let ranges: Vec<(u64, u64)> =
+        opts.ranges.iter().filter(|r| (r.start as u64) < r.end).map(|r| (r.start as u64, r.end)).collect();
+    if let Some(w) = ranges.windows(2).find(|w| w[1].0 < w[0].1) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Ranges must be given in increasing address order without overlapping, found {:#x}..{:#x} after {:#x}..{:#x}",
+                w[1].0, w[1].1, w[0].0, w[0].1
+            ),
+        ));
+    }
+
+    let mut data_writer = RangeDataWriter::new(FilledRanges::new(opts.fill_byte, ranges.clone()), ranges);
+    let warnings = convert_records(records, &mut hasher, &mut data_writer, opts.checks())?;
+    Ok((hasher.finish(), warnings))
+}
+
+/// Computes a checksum or digest of the binary hex2bin produces with `opts`.
+///
+/// Without ranges that is the data from its lowest to its highest address,
+/// gaps filled with the fill byte. With ranges, each range is hashed whole
+/// in the order given, as if the memory were filled with the fill byte
+/// before the data was written, so addresses before the first and after
+/// the last data within a range are hashed too. The ranges must be given
+/// in increasing address order and must not overlap.
+///
+/// Returns the checksum or digest and the warnings of the conversion.
+pub fn hash<R: Read>(reader: R, algorithm: Algorithm, opts: &BinOptions) -> Result<(Vec<u8>, ConvertWarnings)> {
+    hash_records(Records::with_mode(reader, opts.lex_mode), algorithm, opts)
+}
+
+/// Same as `hash`, for an Intel HEX file held in memory
+pub fn hash_slice(input: &[u8], algorithm: Algorithm, opts: &BinOptions) -> Result<(Vec<u8>, ConvertWarnings)> {
+    hash_records(SliceRecords::with_mode(input, opts.lex_mode), algorithm, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::*;
+    use crate::pipeline::AddrRange;
+
+    fn crc(params: CrcParams, bytes: &[u8]) -> u32 {
+        let mut crc = Crc::new(params).unwrap();
+        crc.update(bytes);
+        crc.finish()
+    }
+
+    #[test]
+    fn it_computes_the_check_values_of_the_crc_catalogue() {
+        let check = b"123456789";
+
+        assert_eq!(crc(CrcParams::CRC16_CCITT, check), 0x29b1);
+        assert_eq!(crc(CrcParams::CRC32, check), 0xcbf4_3926);
+
+        let xmodem = CrcParams { init: 0, ..CrcParams::CRC16_CCITT };
+        assert_eq!(crc(xmodem, check), 0x31c3);
+        let kermit = CrcParams { init: 0, reflect: true, ..CrcParams::CRC16_CCITT };
+        assert_eq!(crc(kermit, check), 0x2189);
+        let crc32c = CrcParams { poly: 0x1edc_6f41, ..CrcParams::CRC32 };
+        assert_eq!(crc(crc32c, check), 0xe306_9283);
+        let bzip2 = CrcParams { reflect: false, ..CrcParams::CRC32 };
+        assert_eq!(crc(bzip2, check), 0xfc89_1918);
+        let crc8 = CrcParams { width: 8, poly: 0x07, init: 0, reflect: false, xor_out: 0 };
+        assert_eq!(crc(crc8, check), 0xf4);
+        let crc5_usb = CrcParams { width: 5, poly: 0x05, init: 0x1f, reflect: true, xor_out: 0x1f };
+        assert_eq!(crc(crc5_usb, check), 0x19);
+    }
+
+    #[test]
+    fn it_refuses_crc_widths_outside_1_to_32_bits() {
+        for width in [0, 33] {
+            let params = CrcParams { width, ..CrcParams::CRC32 };
+
+            assert_eq!(Crc::new(params).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            assert!(Hasher::new(Algorithm::Crc(params)).is_err());
+        }
+    }
+
+    #[test]
+    fn it_computes_digests_as_bytes() {
+        let digest = |algorithm, bytes: &[u8]| {
+            let mut hasher = Hasher::new(algorithm).unwrap();
+            hasher.update(bytes);
+            hasher.finish()
+        };
+
+        assert_eq!(digest(Algorithm::Crc(CrcParams::CRC16_CCITT), b"123456789"), [0x29, 0xb1]);
+        assert_eq!(digest(Algorithm::Crc(CrcParams::CRC32), b"123456789"), [0xcb, 0xf4, 0x39, 0x26]);
+        assert_eq!(
+            digest(Algorithm::Md5, b"abc"),
+            [0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1, 0x7f, 0x72]
+        );
+        assert_eq!(digest(Algorithm::Sha256, b"abc")[..8], [0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea]);
+    }
+
+    #[test]
+    fn it_hashes_the_binary_hex2bin_produces() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let bin = include_bytes!("../test/bin/sniffer_nrf52840dk_nrf52840_7cc811f.truth");
+        let algorithm = Algorithm::Crc(CrcParams::CRC32);
+
+        let (digest, _) = hash(&hex[..], algorithm, &BinOptions::default()).unwrap();
+
+        let mut hasher = Hasher::new(algorithm).unwrap();
+        hasher.update(bin);
+        assert_eq!(digest, hasher.finish());
+        assert_eq!(digest, hash_slice(hex, algorithm, &BinOptions::default()).unwrap().0);
+    }
+
+    #[test]
+    fn it_fills_whole_ranges() {
+        let hex = b":020002000102F9\n:01000800AA4D\n:01002000558A\n:00000001FF\n";
+        let opts = BinOptions {
+            fill_byte: 0xee,
+            ranges: vec![AddrRange { start: 0, end: 0x10 }, AddrRange { start: 0x18, end: 0x1c }],
+            ..BinOptions::default()
+        };
+        let algorithm = Algorithm::Crc(CrcParams::CRC32);
+
+        let (digest, _) = hash(&hex[..], algorithm, &opts).unwrap();
+
+        let mut image = vec![0xee; 0x14];
+        image[2..4].copy_from_slice(&[1, 2]);
+        image[8] = 0xaa;
+        let mut hasher = Hasher::new(algorithm).unwrap();
+        hasher.update(&image);
+        assert_eq!(digest, hasher.finish());
+    }
+
+    #[test]
+    fn it_requires_increasing_ranges() {
+        let opts = BinOptions {
+            ranges: vec![AddrRange { start: 0x10, end: 0x20 }, AddrRange { start: 0, end: 0x10 }],
+            ..BinOptions::default()
+        };
+
+        let err = hash(&b":00000001FF\n"[..], Algorithm::Md5, &opts).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
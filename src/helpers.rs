@@ -1,30 +1,51 @@
+/// Value of every ASCII hex digit, 0xff for all other bytes
+const HEX_VALUES: [u8; 256] = {
+    let mut values = [0xffu8; 256];
+    let mut c = 0;
+    while c < 256 {
+        values[c] = match c as u8 {
+            b'0'..=b'9' => c as u8 - b'0',
+            b'A'..=b'F' => c as u8 - b'A' + 10,
+            b'a'..=b'f' => c as u8 - b'a' + 10,
+            _ => 0xff,
+        };
+        c += 1;
+    }
+    values
+};
+
+/// Decodes a pair of hex digits, `None` if either is not a hex digit
 #[inline]
-pub fn atou8(c: u8) -> u8 {
-    if c <= b'9' {
-        c - 48
-    } else if c <= b'F' {
-        c - 55
-    } else if c <= b'f' {
-        c - 87
+pub fn hex_to_u8(bytes: &[u8]) -> Option<u8> {
+    let hi = HEX_VALUES[bytes[0] as usize];
+    let lo = HEX_VALUES[bytes[1] as usize];
+
+    if (hi | lo) & 0xf0 == 0 {
+        Some(hi << 4 | lo)
     } else {
-        0
+        None
     }
 }
 
-#[inline]
-pub fn atou16(c: u8) -> u16 {
-    atou8(c) as u16
-}
+/// Decodes `hex` into `out`, two digits per byte, returns false if `hex`
+/// contains anything but hex digits.
+///
+/// Every byte is decoded before the digits are checked, so that the loop
+/// does not branch.
+pub fn decode_hex_scalar(hex: &[u8], out: &mut [u8]) -> bool {
+    let mut invalid = 0u8;
 
-#[inline]
-pub fn hex_to_u8(bytes: &[u8]) -> u8 {
-    16 * atou8(bytes[0]) + atou8(bytes[1])
-}
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        let hi = HEX_VALUES[pair[0] as usize];
+        let lo = HEX_VALUES[pair[1] as usize];
+        invalid |= hi | lo;
+        *byte = hi << 4 | lo;
+    }
 
-#[inline]
-pub fn hex_to_u16(bytes: &[u8]) -> u16 {
-    4096 * atou16(bytes[0])
-        + 256 * atou16(bytes[1])
-        + 16 * atou16(bytes[2])
-        + atou16(bytes[3])
+    invalid & 0xf0 == 0
 }
+
+#[cfg(not(feature = "simd"))]
+pub use decode_hex_scalar as decode_hex;
+#[cfg(feature = "simd")]
+pub use crate::simd::decode_hex;
@@ -0,0 +1,435 @@
+use std::cmp;
+use std::fmt;
+use std::io::{Read, Result};
+
+use crate::chunks::AddressResolver;
+use crate::datawriter::StartAddr;
+use crate::parser::{Event, Parser};
+use crate::pipeline::AddrRange;
+use crate::record::{LexMode, Record, RecordError, MAX_DATA_LEN};
+use crate::vectors::VectorTable;
+
+/// Bytes read from the input at a time
+const READ_SZ: usize = 4096;
+
+/// Kinds of extended address records found in a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Addressing {
+    /// ExtendedSegmentAddr records, 20-bit addresses
+    pub segment: bool,
+    /// ExtendedLinearAddr records, 32-bit addresses
+    pub linear: bool,
+}
+
+impl fmt::Display for Addressing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.segment, self.linear) {
+            (false, false) => write!(f, "none, 16-bit addresses"),
+            (true, false) => write!(f, "segment"),
+            (false, true) => write!(f, "linear"),
+            (true, true) => write!(f, "segment and linear"),
+        }
+    }
+}
+
+/// Number of lines ending in each style
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndings {
+    pub lf: usize,
+    pub crlf: usize,
+    pub cr: usize,
+}
+
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let styles = [("LF", self.lf), ("CRLF", self.crlf), ("CR", self.cr)];
+        let mut used = styles.iter().filter(|(_, n)| *n > 0);
+
+        match (used.next(), used.next()) {
+            (None, _) => write!(f, "none"),
+            (Some((name, _)), None) => write!(f, "{}", name),
+            _ => {
+                write!(f, "mixed")?;
+                for (i, (name, n)) in styles.iter().filter(|(_, n)| *n > 0).enumerate() {
+                    write!(f, "{}{} {}", if i == 0 { ", " } else { " and " }, n, name)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Summary of an Intel HEX file, see `info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageInfo {
+    /// Contiguous regions of data as (address, length), sorted by address
+    pub segments: Vec<(u32, u64)>,
+    /// Number of records of every type
+    pub records: usize,
+    /// Number of data records by their length in bytes, at index length
+    pub record_lengths: Vec<usize>,
+    /// Start address of the last start address record
+    pub start_addr: Option<StartAddr>,
+    pub addressing: Addressing,
+    pub line_endings: LineEndings,
+    /// False if the input ended without an EndOfFile record
+    pub has_eof: bool,
+    /// Lines on which something was skipped, see `InfoOptions::lex_mode`
+    pub skipped_lines: usize,
+    /// Set when asked to check the vector table, see `InfoOptions`
+    pub vector_table: Option<VectorTable>,
+}
+
+impl ImageInfo {
+    /// Number of addresses holding data
+    pub fn data_bytes(&self) -> u64 {
+        self.segments.iter().map(|(_, len)| len).sum()
+    }
+
+    /// Lowest address holding data
+    pub fn lowest_addr(&self) -> Option<u32> {
+        self.segments.first().map(|(addr, _)| *addr)
+    }
+
+    /// Highest address holding data
+    pub fn highest_addr(&self) -> Option<u32> {
+        self.segments
+            .last()
+            .map(|(addr, len)| (*addr as u64 + len - 1) as u32)
+    }
+
+    /// Share of the addresses from the lowest to the highest that hold data
+    pub fn fill_ratio(&self) -> f64 {
+        match (self.lowest_addr(), self.highest_addr()) {
+            (Some(lowest), Some(highest)) => {
+                self.data_bytes() as f64 / (highest as u64 - lowest as u64 + 1) as f64
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data_records: usize = self.record_lengths.iter().sum();
+        writeln!(
+            f,
+            "records:            {} ({} data)",
+            self.records, data_records
+        )?;
+        writeln!(
+            f,
+            "data:               {} bytes in {} segment{}",
+            self.data_bytes(),
+            self.segments.len(),
+            if self.segments.len() == 1 { "" } else { "s" }
+        )?;
+        if let (Some(lowest), Some(highest)) = (self.lowest_addr(), self.highest_addr()) {
+            writeln!(
+                f,
+                "addresses:          {:#010x}..={:#010x}, {:.1}% filled",
+                lowest,
+                highest,
+                100.0 * self.fill_ratio()
+            )?;
+        }
+        for (addr, len) in &self.segments {
+            writeln!(
+                f,
+                "  segment           {:#010x}..{:#010x} ({} bytes)",
+                addr,
+                *addr as u64 + len,
+                len
+            )?;
+        }
+
+        match self.start_addr {
+            Some(StartAddr::Segment { cs, ip }) => {
+                writeln!(f, "start address:      CS:IP {:04X}:{:04X}", cs, ip)?
+            }
+            Some(StartAddr::Linear(eip)) => writeln!(f, "start address:      {:#010x}", eip)?,
+            None => writeln!(f, "start address:      none")?,
+        }
+
+        write!(f, "record lengths:     ")?;
+        for (i, (len, n)) in self
+            .record_lengths
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, n)| **n > 0)
+            .enumerate()
+        {
+            write!(f, "{}{} bytes: {}", if i == 0 { "" } else { ", " }, len, n)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "extended addresses: {}", self.addressing)?;
+        writeln!(f, "line endings:       {}", self.line_endings)?;
+        writeln!(
+            f,
+            "EndOfFile record:   {}",
+            if self.has_eof { "yes" } else { "missing" }
+        )?;
+        if self.skipped_lines > 0 {
+            writeln!(f, "skipped lines:      {}", self.skipped_lines)?;
+        }
+
+        match &self.vector_table {
+            Some(table) => write!(f, "{}", table),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Counts line endings across chunks of input
+#[derive(Default)]
+struct LineEndingCounter {
+    counts: LineEndings,
+    /// The previous chunk ended with '\r'
+    pending_cr: bool,
+}
+
+impl LineEndingCounter {
+    fn feed(&mut self, input: &[u8]) {
+        for &c in input {
+            match (self.pending_cr, c) {
+                (true, b'\n') => self.counts.crlf += 1,
+                (true, _) => self.counts.cr += 1,
+                (false, b'\n') => self.counts.lf += 1,
+                _ => {}
+            }
+            self.pending_cr = c == b'\r';
+        }
+    }
+
+    fn finish(mut self) -> LineEndings {
+        if self.pending_cr {
+            self.counts.cr += 1;
+        }
+        self.counts
+    }
+}
+
+/// Analyses `info` adds to the summary
+#[derive(Debug, Default, Clone)]
+pub struct InfoOptions {
+    /// Base address of a Cortex-M vector table to check: that the reset
+    /// handler is a Thumb address within the data and matches the start
+    /// address record, and that the initial SP points into RAM
+    pub check_vectors: Option<u32>,
+    /// RAM the initial SP may point into, `CORTEX_M_SRAM` if empty
+    pub ram: Vec<AddrRange>,
+    /// What is accepted between records, as for the conversions
+    pub lex_mode: LexMode,
+}
+
+/// Reads an Intel HEX file and summarises its contents. Unlike the
+/// conversions this does not fail on a missing EndOfFile record.
+pub fn info<R: Read>(reader: R) -> Result<ImageInfo> {
+    info_with(reader, &InfoOptions::default())
+}
+
+pub fn info_with<R: Read>(mut reader: R, opts: &InfoOptions) -> Result<ImageInfo> {
+    let mut parser = Parser::with_mode(opts.lex_mode);
+    let mut resolver = AddressResolver::new();
+    let mut line_endings = LineEndingCounter::default();
+    let mut buf = vec![0u8; READ_SZ];
+
+    let mut info = ImageInfo {
+        segments: Vec::new(),
+        records: 0,
+        record_lengths: vec![0; MAX_DATA_LEN + 1],
+        start_addr: None,
+        addressing: Addressing::default(),
+        line_endings: LineEndings::default(),
+        has_eof: false,
+        skipped_lines: 0,
+        vector_table: None,
+    };
+    /* The first two vector table entries, as far as the data covers them */
+    let mut vectors = [None; 8];
+    /* Data ranges as [start, end), merged with the previous one when contiguous */
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    while !parser.is_done() {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        line_endings.feed(&buf[..n]);
+
+        for event in parser.feed(&buf[..n]) {
+            let record = match event {
+                Event::Record(_, record) => record,
+                Event::Error(e) => return Err(e.into()),
+            };
+            info.records += 1;
+
+            match record {
+                Record::Data { addr, bytes } => {
+                    info.record_lengths[bytes.len()] += 1;
+                    for (addr, piece) in resolver.resolve(addr, &bytes) {
+                        let (start, end) = (addr as u64, addr as u64 + piece.len() as u64);
+                        if let Some(base) = opts.check_vectors {
+                            for (i, entry) in vectors.iter_mut().enumerate() {
+                                let a = base as u64 + i as u64;
+                                if entry.is_none() && start <= a && a < end {
+                                    *entry = Some(piece[(a - start) as usize]);
+                                }
+                            }
+                        }
+                        match ranges.last_mut() {
+                            Some(last) if last.1 == start => last.1 = end,
+                            _ if start < end => ranges.push((start, end)),
+                            _ => {}
+                        }
+                    }
+                }
+                Record::StartSegmentAddr { cs, ip } => {
+                    info.start_addr = Some(StartAddr::Segment { cs, ip })
+                }
+                Record::StartLinearAddr(eip) => info.start_addr = Some(StartAddr::Linear(eip)),
+                Record::ExtendedSegmentAddr(_) => info.addressing.segment = true,
+                Record::ExtendedLinearAddr(_) => info.addressing.linear = true,
+                Record::EndOfFile => info.has_eof = true,
+            }
+            resolver.apply(&record);
+        }
+    }
+
+    match parser.finish() {
+        Err(e) if e.error != RecordError::MissingEndOfFile => return Err(e.into()),
+        _ => {}
+    }
+
+    ranges.sort_unstable();
+    for (start, end) in ranges {
+        match info.segments.last_mut() {
+            Some((addr, len)) if start <= *addr as u64 + *len => {
+                *len = cmp::max(*len, end - *addr as u64);
+            }
+            _ => info.segments.push((start as u32, end - start)),
+        }
+    }
+    info.line_endings = line_endings.finish();
+    info.skipped_lines = parser.warnings();
+    info.vector_table = opts.check_vectors.map(|base| {
+        VectorTable::check(base, &vectors, &info.segments, info.start_addr, &opts.ram)
+    });
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::info::*;
+
+    #[test]
+    fn it_summarises_a_file() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let bin = include_bytes!("../test/bin/sniffer_nrf52840dk_nrf52840_7cc811f.truth");
+
+        let info = info(&hex[..]).unwrap();
+
+        assert_eq!(info.segments, vec![(0, bin.len() as u64)]);
+        assert_eq!(info.records, 2178);
+        assert_eq!(info.record_lengths.iter().sum::<usize>(), 2176);
+        assert_eq!(
+            info.start_addr,
+            Some(StartAddr::Segment { cs: 0, ip: 0x3605 })
+        );
+        assert_eq!(info.addressing, Addressing::default());
+        assert_eq!(
+            info.line_endings,
+            LineEndings {
+                crlf: 2178,
+                ..LineEndings::default()
+            }
+        );
+        assert!(info.has_eof);
+        assert_eq!(info.fill_ratio(), 1.0);
+    }
+
+    #[test]
+    fn it_checks_the_vector_table_if_asked() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let opts = InfoOptions {
+            check_vectors: Some(0),
+            ..InfoOptions::default()
+        };
+
+        let table = info_with(&hex[..], &opts).unwrap().vector_table.unwrap();
+
+        assert_eq!(table.initial_sp, Some(0x2003_ea90));
+        assert_eq!(table.reset, Some(0x3605));
+        assert!(table.problems.is_empty());
+
+        let opts = InfoOptions {
+            check_vectors: Some(0),
+            ram: vec![AddrRange {
+                start: 0x2000_0000,
+                end: 0x2001_0000,
+            }],
+            ..InfoOptions::default()
+        };
+        let info = info_with(&hex[..], &opts).unwrap();
+        assert_eq!(
+            info.vector_table.unwrap().problems,
+            vec![crate::vectors::VectorProblem::SpOutsideRam(0x2003_ea90)]
+        );
+        assert_eq!(info_with(&hex[..], &InfoOptions::default()).unwrap().vector_table, None);
+    }
+
+    #[test]
+    fn it_skips_comments_if_asked() {
+        let hex = include_bytes!("../test/lenient.in");
+        let opts = InfoOptions {
+            lex_mode: LexMode::Lenient(crate::Comments::all()),
+            ..InfoOptions::default()
+        };
+
+        assert!(info(&hex[..]).is_err());
+        let info = info_with(&hex[..], &opts).unwrap();
+        assert!(info.has_eof);
+        assert!(info.skipped_lines > 0);
+    }
+
+    #[test]
+    fn it_sorts_and_merges_segments() {
+        let hex = b":020000040001F9\n:02000000CCDD55\n:020000040000FA\n:02FFFE00AABB9C\n:02000200EEFF0F\r\n";
+
+        let info = info(&hex[..]).unwrap();
+
+        assert_eq!(info.segments, vec![(0x2, 2), (0xFFFE, 4)]);
+        assert_eq!(
+            (info.lowest_addr(), info.highest_addr()),
+            (Some(0x2), Some(0x10001))
+        );
+        assert_eq!(info.data_bytes(), 6);
+        assert_eq!(info.record_lengths[2], 3);
+        assert_eq!(
+            info.addressing,
+            Addressing {
+                segment: false,
+                linear: true
+            }
+        );
+        assert_eq!(
+            info.line_endings,
+            LineEndings {
+                lf: 4,
+                crlf: 1,
+                cr: 0
+            }
+        );
+        assert!(!info.has_eof);
+        assert_eq!(info.line_endings.to_string(), "mixed, 4 LF and 1 CRLF");
+    }
+
+    #[test]
+    fn it_fails_on_invalid_records() {
+        assert!(info(&b":0100000000FF\n:0100010000FF\n"[..]).is_err());
+        assert!(info(&b":0100000000FF\n:0100"[..]).is_err());
+    }
+}
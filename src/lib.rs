@@ -1,384 +1,72 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::needless_range_loop)]
 
+#[cfg(feature = "async")]
+mod async_io;
+mod chunks;
+#[cfg(feature = "std")]
+mod color;
+#[cfg(feature = "std")]
 mod datawriter;
+#[cfg(feature = "std")]
+mod hash;
+#[cfg(feature = "std")]
 mod ringbuffer;
 mod helpers;
-
-use std::cmp;
-use std::io::{BufWriter, Read, Write};
-use std::io::{Error, ErrorKind, Result};
-
-use datawriter::*;
-use helpers::*;
-
-const COLON: usize = 1;
-
-// header offsets
-const HDR_LEN: usize = COLON;
-const HDR_LEN_SZ: usize = 2;
-const HDR_ADDR: usize = HDR_LEN + HDR_LEN_SZ;
-const HDR_ADDR_SZ: usize = 4;
-const HDR_TYPE: usize = HDR_ADDR + HDR_ADDR_SZ;
-const HDR_TYPE_SZ: usize = 2;
-
-const RECORD_HEADER_SZ: usize = COLON + HDR_LEN_SZ + HDR_ADDR_SZ + HDR_TYPE_SZ;
-const CHECKSUM_SZ: usize = 2;
-
-const DATA_ROW_HEX_SZ: usize = 32;
-
-// data row offsets
-const EXT_LINEAR_ADDR_UPPER_ADDR: usize = COLON + 8;
-const EXT_LINEAR_ADDR_UPPER_ADDR_SZ: usize = 4;
-const EXT_SEGMENT_ADDR_UPPER_ADDR: usize = COLON + 8;
-const EXT_SEGMENT_ADDR_UPPER_ADDR_SZ: usize = 4;
-const DATA_START: usize = RECORD_HEADER_SZ;
-
-#[derive(Debug, PartialEq, Eq)]
-enum RecordType {
-    Data,
-    EndOfFile,
-    ExtendedSegmentAddr,
-    StartSegmentAddr,
-    ExtendedLinearAddr,
-    StartLinearAddr,
-
-    Unknown,
-}
-
-impl From<u8> for RecordType {
-    fn from(b: u8) -> RecordType {
-        match b {
-            0x00 => RecordType::Data,
-            0x01 => RecordType::EndOfFile,
-            0x02 => RecordType::ExtendedSegmentAddr,
-            0x03 => RecordType::StartSegmentAddr,
-            0x04 => RecordType::ExtendedLinearAddr,
-            0x05 => RecordType::StartLinearAddr,
-            _ => RecordType::Unknown,
-        }
-    }
-}
-
-impl RecordType {
-    fn fixed_size(self) -> usize {
-        match self {
-            RecordType::EndOfFile => COLON + 10,
-            RecordType::ExtendedSegmentAddr => COLON + 14,
-            RecordType::StartSegmentAddr => COLON + 18,
-            RecordType::ExtendedLinearAddr => COLON + 14,
-            RecordType::StartLinearAddr => COLON + 18,
-            _ => 0,
-        }
-    }
-}
-
-#[inline]
-fn maybe_fetch<R: Read, const SZ: usize>(
-    rb: &mut ringbuffer::RingBuffer<SZ>,
-    reader: &mut R,
-    need: usize,
-) -> Result<()> {
-    if rb.len() >= need {
-        return Ok(());
-    }
-
-    rb.fill(reader)?;
-
-    if rb.len() < need {
-        Err(Error::new(
-            ErrorKind::Other,
-            "Expected more bytes to be available",
-        ))
-    } else {
-        Ok(())
-    }
-}
-
-#[derive(Default, Clone, Copy)]
-struct DataRow {
-    addr: u16,
-    data: [u8; 32],
-    len: u8,
-}
-
-impl DataRow {
-    fn new(addr: u16, bytes: &[u8]) -> DataRow {
-        let mut d = DataRow {
-            addr,
-            data: [0u8; 32],
-            len: bytes.len() as u8,
-        };
-        d.data[..d.len as usize].copy_from_slice(bytes);
-        d
-    }
-}
-
-struct DataRowCache<'a, W: Write, DWR: DataWriter<W>, const LINES: usize> {
-    cache: [DataRow; LINES],
-    read_idx: usize,
-    write_idx: usize,
-    data_writer: &'a mut DWR,
-    _data_writer_writer: std::marker::PhantomData<W>,
-}
-
-impl<'a, W: Write, DWR: DataWriter<W>, const LINES: usize> DataRowCache<'a, W, DWR, LINES> {
-    fn new(data_writer: &mut DWR) -> DataRowCache<W, DWR, LINES>
-    {
-        DataRowCache {
-            cache: [DataRow::default(); LINES],
-            read_idx: 0,
-            write_idx: 0,
-            data_writer,
-            _data_writer_writer: std::marker::PhantomData,
-        }
-    }
-
-    fn push(&mut self, addr: u16, bytes: &[u8]) {
-        self.cache[self.write_idx] = DataRow::new(addr, bytes);
-        self.write_idx = (self.write_idx + 1) % LINES;
-    }
-
-    fn push_front(&mut self, addr: u16, bytes: &[u8]) {
-        self.read_idx = (self.read_idx.wrapping_sub(1)) % LINES;
-        self.cache[self.read_idx] = DataRow::new(addr, bytes);
-    }
-
-    fn peek(&self) -> &DataRow {
-        return &self.cache[self.read_idx];
-    }
-
-    fn pop(&mut self) -> DataRow {
-        let d = self.cache[self.read_idx];
-        self.read_idx = (self.read_idx + 1) % LINES;
-        d
-    }
-
-    fn available(&self) -> usize {
-        let mut bytes = 0;
-
-        for i in 0..self.len() {
-            let idx = (self.read_idx + i) % LINES;
-            bytes += self.cache[idx].len as usize;
-        }
-
-        bytes
-    }
-
-    fn len(&self) -> usize {
-        if self.read_idx > self.write_idx {
-            LINES - self.read_idx + self.write_idx
-        } else {
-            self.write_idx - self.read_idx
-        }
-    }
-
-    fn build_and_print_row(&mut self, writer: &mut W, addr_offset: i64) -> Result<usize> {
-        let avail = self.available();
-
-        /* Not possible, return available bytes */
-        if avail < DATA_ROW_HEX_SZ {
-            return Ok(avail);
-        }
-
-        let mut buf: [u8; DATA_ROW_HEX_SZ] = [0u8; DATA_ROW_HEX_SZ];
-        let mut len = 0;
-        let print_addr = self.peek().addr;
-
-        while len < DATA_ROW_HEX_SZ as u8 {
-            let d = self.pop();
-            let to_write = cmp::min(d.len as usize, DATA_ROW_HEX_SZ - len as usize);
-
-            buf[len as usize..len as usize + to_write].copy_from_slice(&d.data[..to_write]);
-            len += to_write as u8;
-
-            if to_write as u8 != d.len {
-                let num_pushback_bytes = (d.data[to_write..].len() / 2) as u16;
-                self.push_front(d.addr + num_pushback_bytes, &d.data[to_write..]);
-            }
-        }
-
-        /* Row address correction should not be applied here since this was an aligned
-         * data row that was just missing some data
-         */
-        self.data_writer.write(writer, addr_offset + print_addr as i64, &buf[..])?;
-
-        Ok(self.available())
-    }
-
-    fn dump_cache(&mut self, writer: &mut W, addr_offset: i64) -> Result<()> {
-        /* Build a full row if possible */
-        self.build_and_print_row(writer, addr_offset)?;
-        /* Dump remaining rows. Logically it should only be 1 */
-        while self.read_idx != self.write_idx {
-            let row = self.cache[self.read_idx];
-            self.read_idx = (1 + self.read_idx) % LINES;
-            self.data_writer.write(
-                writer,
-                addr_offset + row.addr as i64,
-                &row.data[..row.len as usize],
-            )?;
-        }
-
-        Ok(())
-    }
-}
-
-fn process<R: Read, W: Write, DWR: DataWriter<BufWriter<W>>>(
-    mut reader: R,
-    writer: W,
-    data_writer: &mut DWR,
-) -> Result<()> {
-    const BUF_SZ: usize = 4096;
-
-    let mut rb: ringbuffer::RingBuffer<BUF_SZ> = ringbuffer::RingBuffer::new();
-    let mut addr_offset: i64 = 0;
-
-    let mut writer = BufWriter::new(writer);
-
-    rb.fill(&mut reader)?;
-
-    let mut data_cache: DataRowCache<_, _, 8> = DataRowCache::new(data_writer);
-    let mut row_addr_correction: i64 = 0;
-
-    loop {
-        maybe_fetch(&mut rb, &mut reader, RECORD_HEADER_SZ)?;
-
-        let buf = rb.wrapping_peek(RECORD_HEADER_SZ).unwrap();
-
-        let record_type = RecordType::from(hex_to_u8(&buf[HDR_TYPE..HDR_TYPE + HDR_TYPE_SZ]));
-
-        match record_type {
-            RecordType::Unknown => {
-                eprintln!(
-                    "Found unknown record type: {:#02x}",
-                    hex_to_u8(&buf[HDR_TYPE..HDR_TYPE + HDR_TYPE_SZ])
-                );
-                eprintln!("Surrounding bytes: {:?}", unsafe {
-                    std::str::from_utf8_unchecked(&buf[0..RECORD_HEADER_SZ])
-                });
-                return Err(Error::new(ErrorKind::Other, "Unknown record type"));
-            }
-            RecordType::Data => {
-                let data_len = 2 * hex_to_u8(&buf[HDR_LEN..HDR_LEN + HDR_LEN_SZ]) as usize;
-                let sz = CHECKSUM_SZ + RECORD_HEADER_SZ + data_len;
-
-                maybe_fetch(&mut rb, &mut reader, sz)?;
-
-                let buf = rb.wrapping_peek(sz).unwrap();
-
-                let addr = hex_to_u16(&buf[HDR_ADDR..HDR_ADDR + HDR_ADDR_SZ]);
-
-                /* Build and dump a full row if possible */
-                let avail = data_cache.build_and_print_row(&mut writer, addr_offset)?;
-
-                /* If the cache is empty then we have no more row correction to do */
-                if avail == 0 {
-                    row_addr_correction = 0;
-                }
-
-                if avail > 0 && avail < DATA_ROW_HEX_SZ {
-                    /* We have partial data in the cache that needs more data
-                     * to complete a full line
-                     */
-                    data_cache.push(addr, &buf[DATA_START..sz - CHECKSUM_SZ]);
-                } else if data_len % 32 != 0 {
-                    data_cache.push(addr, &buf[DATA_START..sz - CHECKSUM_SZ]);
-                    /* Adjust addresses of following data lines since we now
-                     * adjusted the data contents of one line from partial
-                     * to full
-                     */
-                    row_addr_correction -= (data_len / 2) as i64;
-                } else {
-                    data_cache.push((addr_offset + row_addr_correction + addr as i64) as u16, &buf[DATA_START..sz - CHECKSUM_SZ]);
-                }
-
-                rb.consume(sz).unwrap();
-            }
-            // This record affects the following data addresses
-            RecordType::ExtendedLinearAddr => {
-                let sz = RecordType::ExtendedLinearAddr.fixed_size();
-
-                maybe_fetch(&mut rb, &mut reader, sz)?;
-
-                let buf = rb.wrapping_peek(sz).unwrap();
-
-                /* Dump cache before changing section */
-                data_cache.dump_cache(&mut writer, addr_offset + row_addr_correction)?;
-
-                row_addr_correction = 0;
-                addr_offset = (hex_to_u16(
-                    &buf[EXT_LINEAR_ADDR_UPPER_ADDR
-                        ..EXT_LINEAR_ADDR_UPPER_ADDR + EXT_LINEAR_ADDR_UPPER_ADDR_SZ],
-                ) as i64)
-                    << 16;
-
-                rb.consume(sz).unwrap();
-            }
-            // This record affects the following data addresses
-            RecordType::ExtendedSegmentAddr => {
-                let sz = RecordType::ExtendedSegmentAddr.fixed_size();
-
-                maybe_fetch(&mut rb, &mut reader, sz)?;
-
-                let buf = rb.wrapping_peek(sz).unwrap();
-
-                let segment_addr = hex_to_u16(
-                    &buf[EXT_SEGMENT_ADDR_UPPER_ADDR
-                        ..EXT_SEGMENT_ADDR_UPPER_ADDR + EXT_SEGMENT_ADDR_UPPER_ADDR_SZ],
-                );
-
-                /* Dump cache before changing section */
-                data_cache.dump_cache(&mut writer, addr_offset + row_addr_correction)?;
-
-                row_addr_correction = 0;
-                addr_offset = (segment_addr as i64) << 4;
-
-                rb.consume(sz).unwrap();
-            }
-            // Skip records that do not affect output
-            rt @ (RecordType::StartLinearAddr | RecordType::StartSegmentAddr) => {
-                let sz = rt.fixed_size();
-
-                maybe_fetch(&mut rb, &mut reader, sz)?;
-
-                rb.consume(sz).unwrap();
-            }
-            RecordType::EndOfFile => {
-                data_cache.dump_cache(&mut writer, addr_offset)?;
-                writer.flush()?;
-                break;
-            }
-        }
-
-        loop {
-            maybe_fetch(&mut rb, &mut reader, 1)?;
-
-            match rb.peek(1).unwrap()[0] as char {
-                '\r' => {
-                    rb.consume(1).unwrap();
-                }
-                '\n' => {
-                    rb.consume(1).unwrap();
-                }
-                _ => break,
-            };
-        }
-    }
-
-    Ok(())
-}
-
-pub fn hex2dump<R: Read, W: Write>(reader: R, writer: W) -> Result<()> {
-    let mut hex_writer = HexDataWriter::new();
-    process(reader, writer, &mut hex_writer)
-}
-
-pub fn hex2bin<R: Read, W: Write>(reader: R, writer: W, fill_byte: u8) -> Result<()> {
-    let mut hex_writer = BinDataWriter::new(fill_byte);
-    process(reader, writer, &mut hex_writer)
-}
-
-#[cfg(test)]
+#[cfg(feature = "std")]
+mod info;
+#[cfg(feature = "std")]
+mod overlaps;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod parser;
+#[cfg(feature = "std")]
+mod pipeline;
+mod record;
+#[cfg(feature = "std")]
+mod records;
+#[cfg(feature = "simd")]
+mod simd;
+#[cfg(feature = "std")]
+mod stamp;
+#[cfg(feature = "std")]
+mod vectors;
+#[cfg(feature = "std")]
+mod verify;
+
+#[cfg(feature = "async")]
+pub use async_io::{convert_async, hex2bin_async, hex2dump_async};
+pub use chunks::AddressResolver;
+#[cfg(feature = "std")]
+pub use chunks::DataChunks;
+#[cfg(feature = "std")]
+pub use datawriter::{BinDataWriter, DataWriter, IntelHexDataWriter, StartAddr};
+#[cfg(feature = "std")]
+pub use hash::{hash, hash_slice, Algorithm, Crc, CrcParams, Hasher};
+#[cfg(feature = "std")]
+pub use info::{info, info_with, Addressing, ImageInfo, InfoOptions, LineEndings};
+#[cfg(feature = "std")]
+pub use overlaps::{Overlap, OverlapChecker};
+#[cfg(feature = "parallel")]
+pub use parallel::{convert_parallel, hex2bin_parallel, hex2dump_parallel, hex2hex_parallel};
+pub use parser::{Event, Parser};
+#[cfg(feature = "std")]
+pub use pipeline::{
+    convert, convert_slice, hex2bin, hex2bin_slice, hex2bin_with, hex2dump, hex2dump_slice,
+    hex2dump_with, hex2hex_slice, hex2hex_with, AddrRange, BinOptions, Compat, ConvertWarnings,
+    DumpOptions, HexOptions, MissingEof,
+};
+pub use record::{Comments, DataBytes, LexMode, LineError, Record, RecordError, SliceRecords};
+#[cfg(feature = "std")]
+pub use records::Records;
+#[cfg(feature = "std")]
+pub use stamp::{stamp, stamp_slice, Endian, StampOptions};
+#[cfg(feature = "std")]
+pub use vectors::{VectorProblem, VectorTable, CORTEX_M_SRAM};
+#[cfg(feature = "std")]
+pub use verify::{verify, Issue, Problem, Report, Severity};
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::*;
     use std::fs::File;
@@ -439,6 +127,48 @@ mod tests {
         run_test(test, "hex", |infile, outfile| { hex2dump(infile, outfile) });
     }
 
+    fn run_py_test(test: &'static str) {
+        run_test(test, "py", |infile, outfile| {
+            let opts = DumpOptions { compat: Some(Compat::Python), ..DumpOptions::default() };
+            hex2dump_with(infile, outfile, &opts).map(|_| ())
+        });
+    }
+
+    fn run_squeeze_test(test: &'static str) {
+        run_test(test, "squeeze", |infile, outfile| {
+            let opts = DumpOptions { squeeze: true, ..DumpOptions::default() };
+            hex2dump_with(infile, outfile, &opts).map(|_| ())
+        });
+    }
+
+    fn run_annotate_test(test: &'static str) {
+        run_test(test, "annotate", |infile, outfile| {
+            let opts = DumpOptions { annotate: true, ..DumpOptions::default() };
+            hex2dump_with(infile, outfile, &opts).map(|_| ())
+        });
+    }
+
+    fn test_ranges() -> Vec<AddrRange> {
+        ["0x1005..0x1023", "0x87C4+0x100", "0x2+3"]
+            .iter()
+            .map(|r| r.parse().unwrap())
+            .collect()
+    }
+
+    fn run_range_test(test: &'static str) {
+        run_test(test, "range", |infile, outfile| {
+            let opts = DumpOptions { ranges: test_ranges(), ..DumpOptions::default() };
+            hex2dump_with(infile, outfile, &opts).map(|_| ())
+        });
+    }
+
+    fn run_range_bin_test(test: &'static str) {
+        run_test(test, "range-bin", |infile, outfile| {
+            let opts = BinOptions { ranges: test_ranges(), ..BinOptions::default() };
+            hex2bin_with(infile, outfile, &opts).map(|_| ())
+        });
+    }
+
     fn run_bin_test(test: &'static str) {
         run_test(test, "bin", |infile, outfile| { hex2bin(infile, outfile, 0xff) });
     }
@@ -497,4 +227,308 @@ mod tests {
     fn it_equals_py_hex2bin_output_addrspace_gap_mid_partial() {
         run_bin_test("addrspace-gap-mid-partial");
     }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_partial_line() {
+        run_py_test("partial_line");
+    }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_nrf() {
+        run_py_test("sniffer_nrf52840dk_nrf52840_7cc811f");
+    }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_nina() {
+        run_py_test("NINA-W15X-SW-4.0.0-006");
+    }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_addrspace_gap_start() {
+        run_py_test("addrspace-gap-start");
+    }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_addrspace_gap_mid() {
+        run_py_test("addrspace-gap-mid");
+    }
+
+    #[test]
+    fn it_equals_py_hex2dump_compat_output_addrspace_gap_mid_partial() {
+        run_py_test("addrspace-gap-mid-partial");
+    }
+
+    #[test]
+    fn it_squeezes_repeated_rows_within_segments() {
+        run_squeeze_test("squeeze");
+    }
+
+    #[test]
+    fn it_squeezes_nothing_without_repeated_rows() {
+        run_squeeze_test("partial_line");
+    }
+
+    #[test]
+    fn it_annotates_gaps_and_segments() {
+        run_annotate_test("addrspace-gap-mid-partial");
+    }
+
+    #[test]
+    fn it_annotates_gaps_between_extended_linear_addresses() {
+        run_annotate_test("squeeze");
+    }
+
+    #[test]
+    fn it_parses_addr_ranges() {
+        assert_eq!("0x1000..0x2000".parse(), Ok(AddrRange { start: 0x1000, end: 0x2000 }));
+        assert_eq!("4096+16".parse(), Ok(AddrRange { start: 0x1000, end: 0x1010 }));
+        assert_eq!("0x10001000+0x100".parse(), Ok(AddrRange { start: 0x10001000, end: 0x10001100 }));
+        assert!("0x2000..0x1000".parse::<AddrRange>().is_err());
+        assert_eq!("0xFFFFFF00+0x100".parse(), Ok(AddrRange { start: 0xFFFFFF00, end: 1 << 32 }));
+        assert_eq!("0xFFFFFF00..0x100000000".parse(), Ok(AddrRange { start: 0xFFFFFF00, end: 1 << 32 }));
+        assert!("0xFFFFFFFF+2".parse::<AddrRange>().is_err());
+        assert!("0..0x100000001".parse::<AddrRange>().is_err());
+        assert!("0x1000".parse::<AddrRange>().is_err());
+    }
+
+    #[test]
+    fn it_clips_dump_to_ranges() {
+        run_range_test("sniffer_nrf52840dk_nrf52840_7cc811f");
+    }
+
+    #[test]
+    fn it_clips_bin_to_ranges() {
+        run_range_bin_test("sniffer_nrf52840dk_nrf52840_7cc811f");
+    }
+
+    #[test]
+    fn it_rewrites_hex_within_ranges() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let mut rewritten = Vec::new();
+
+        hex2hex_slice(hex, &mut rewritten, &HexOptions { ranges: test_ranges(), ..HexOptions::default() }).unwrap();
+
+        let (mut bin, mut truth) = (Vec::new(), Vec::new());
+        hex2bin(&rewritten[..], &mut bin, 0xff).unwrap();
+        hex2bin_slice(hex, &mut truth, &BinOptions { ranges: test_ranges(), ..BinOptions::default() }).unwrap();
+        assert_eq!(bin, truth);
+    }
+
+    #[test]
+    fn it_continues_linear_records_past_offset_0xffff() {
+        let hex = b":020000040000FA\n:02FFFF00AABB9B\n:00000001FF\n";
+        let mut bin = Vec::new();
+
+        hex2bin(&hex[..], &mut bin, 0xff).unwrap();
+
+        /* Wrapped at 16 bits, 0xBB would land at 0 and the image start there */
+        assert_eq!(bin, [0xAA, 0xBB]);
+    }
+
+    fn strip_ansi(s: &str) -> String {
+        let mut out = String::new();
+        let mut in_escape = false;
+        for c in s.chars() {
+            match (in_escape, c) {
+                (false, '\x1b') => in_escape = true,
+                (false, c) => out.push(c),
+                (true, 'm') => in_escape = false,
+                (true, _) => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn it_colors_without_changing_the_layout() {
+        let input = File::open("test/partial_line.in").unwrap();
+        let truth = std::fs::read_to_string("test/hex/partial_line.truth").unwrap();
+        let mut output = Vec::new();
+
+        let opts = DumpOptions { color: true, ..DumpOptions::default() };
+        hex2dump_with(input, &mut output, &opts).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\x1b[34mFF\x1b[0m"));
+        assert_eq!(strip_ansi(&output), truth);
+    }
+
+    #[test]
+    fn it_highlights_patterns_across_rows() {
+        let input = File::open("test/partial_line.in").unwrap();
+        let mut output = Vec::new();
+
+        /* "ia" ends the first row, "li" starts the second */
+        let opts = DumpOptions {
+            color: true,
+            highlight_bytes: b"iali".to_vec(),
+            ..DumpOptions::default()
+        };
+        hex2dump_with(input, &mut output, &opts).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0].matches(";7m").count(), 4);
+        assert_eq!(lines[1].matches(";7m").count(), 4);
+        assert_eq!(output.matches(";7m").count(), 8);
+    }
+
+    /// Collects segments as (address, bytes) and checks the order of calls
+    #[derive(Default)]
+    struct SegmentCollector {
+        segments: Vec<(u32, Vec<u8>)>,
+        open: bool,
+        start_addr: Option<StartAddr>,
+        finished: bool,
+    }
+
+    impl<W: Write> DataWriter<W> for SegmentCollector {
+        fn write(&mut self, _writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+            assert!(self.open && !bytes.is_empty() && bytes.len() <= 16);
+            let (start, data) = self.segments.last_mut().unwrap();
+            assert_eq!(*start as usize + data.len(), addr as usize);
+            data.extend_from_slice(bytes);
+            Ok(())
+        }
+
+        fn begin_segment(&mut self, _writer: &mut W, addr: u32) -> Result<()> {
+            assert!(!self.open);
+            self.open = true;
+            self.segments.push((addr, Vec::new()));
+            Ok(())
+        }
+
+        fn end_segment(&mut self, _writer: &mut W) -> Result<()> {
+            assert!(self.open);
+            self.open = false;
+            Ok(())
+        }
+
+        fn start_addr(&mut self, _writer: &mut W, start_addr: StartAddr) -> Result<()> {
+            self.start_addr = Some(start_addr);
+            Ok(())
+        }
+
+        fn finish(&mut self, _writer: &mut W) -> Result<()> {
+            assert!(!self.open && !self.finished);
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_converts_into_a_custom_data_writer() {
+        let input = File::open("test/addrspace-gap-mid.in").unwrap();
+        let mut collector = SegmentCollector::default();
+
+        convert(input, std::io::sink(), &mut collector).unwrap();
+
+        assert!(collector.finished);
+        let segments: Vec<(u32, usize)> =
+            collector.segments.iter().map(|(addr, data)| (*addr, data.len())).collect();
+        assert_eq!(segments, vec![(0x0000, 0x90), (0xF100, 0xE0)]);
+    }
+
+    #[test]
+    fn it_converts_decoded_bytes() {
+        let input = File::open("hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex").unwrap();
+        let bin = std::fs::read("test/bin/sniffer_nrf52840dk_nrf52840_7cc811f.truth").unwrap();
+        let mut collector = SegmentCollector::default();
+
+        convert(input, std::io::sink(), &mut collector).unwrap();
+
+        assert_eq!(collector.segments, vec![(0, bin)]);
+        assert_eq!(collector.start_addr, Some(StartAddr::Segment { cs: 0x0000, ip: 0x3605 }));
+    }
+
+    #[test]
+    fn it_converts_slices_like_readers() {
+        for test in ["partial_line", "addrspace-gap-mid-partial", "sniffer_nrf52840dk_nrf52840_7cc811f"] {
+            let path = match test {
+                "sniffer_nrf52840dk_nrf52840_7cc811f" => format!("hex-examples/{}.hex", test),
+                _ => format!("test/{}.in", test),
+            };
+            let hex = std::fs::read(path).unwrap();
+
+            let (mut from_slice, mut from_reader) = (Vec::new(), Vec::new());
+            hex2dump_slice(&hex, &mut from_slice, &DumpOptions::default()).unwrap();
+            hex2dump_with(&hex[..], &mut from_reader, &DumpOptions::default()).unwrap();
+            assert_eq!(from_slice, from_reader);
+
+            let (mut from_slice, mut from_reader) = (Vec::new(), Vec::new());
+            hex2bin_slice(&hex, &mut from_slice, &BinOptions::default()).unwrap();
+            hex2bin_with(&hex[..], &mut from_reader, &BinOptions::default()).unwrap();
+            assert_eq!(from_slice, from_reader);
+        }
+    }
+
+    #[test]
+    fn it_checks_for_overlaps_when_converting() {
+        let opts = BinOptions { check_overlaps: true, ..BinOptions::default() };
+
+        /* Repeated data is left out, so the binary is written in order */
+        let hex = b":0400000001020304F2\n:020002000304F5\n:0100040005F6\n:00000001FF\n";
+        let mut bin = Vec::new();
+        let warnings = hex2bin_with(&hex[..], &mut bin, &opts).unwrap();
+        assert_eq!(bin, [1, 2, 3, 4, 5]);
+        assert_eq!(
+            warnings.overlaps,
+            vec![Problem {
+                line: 2,
+                issue: Issue::Overlap { start: 2, end: 4, other_line: 1, identical: true }
+            }]
+        );
+        assert!(hex2bin_with(&hex[..], &mut Vec::new(), &BinOptions::default()).is_err());
+
+        let hex = b":0400000001020304F2\n:02000200AABB97\n:00000001FF\n";
+        let err = hex2bin_slice(&hex[..], &mut Vec::new(), &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Data at 0x00000002..0x00000004 conflicts with the data on line 1 on line 2"
+        );
+    }
+
+    #[test]
+    fn it_converts_input_missing_end_of_file_if_asked() {
+        let hex = b":03000000010203F7\n";
+
+        let err = hex2bin_with(&hex[..], &mut Vec::new(), &BinOptions::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Expected an EndOfFile record on line 2");
+
+        for missing_eof in [MissingEof::Warn, MissingEof::Ignore] {
+            let opts = BinOptions { missing_eof, ..BinOptions::default() };
+            let (mut from_reader, mut from_slice) = (Vec::new(), Vec::new());
+            let warnings = hex2bin_with(&hex[..], &mut from_reader, &opts).unwrap();
+            hex2bin_slice(&hex[..], &mut from_slice, &opts).unwrap();
+            assert_eq!((from_reader, from_slice), (vec![1, 2, 3], vec![1, 2, 3]));
+            assert_eq!(
+                warnings.missing_eof.is_some(),
+                missing_eof == MissingEof::Warn
+            );
+
+            /* The partial row is written out */
+            let opts = DumpOptions { missing_eof, ..DumpOptions::default() };
+            let mut dump = Vec::new();
+            hex2dump_with(&hex[..], &mut dump, &opts).unwrap();
+            assert!(String::from_utf8(dump).unwrap().starts_with("0x00000000  01 02 03"));
+        }
+    }
+
+    #[test]
+    fn it_rejects_data_after_end_of_file_if_asked() {
+        let hex = b":0100000000FF\n:00000001FF\n\n:0100000000FF\n";
+        let opts = BinOptions { reject_data_after_eof: true, ..BinOptions::default() };
+
+        hex2bin_with(&hex[..], &mut Vec::new(), &BinOptions::default()).unwrap();
+        for res in [
+            hex2bin_with(&hex[..], &mut Vec::new(), &opts),
+            hex2bin_slice(&hex[..], &mut Vec::new(), &opts),
+        ] {
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                "Unexpected data after the EndOfFile record on line 4"
+            );
+        }
+        hex2bin_with(&b":00000001FF\r\n \r\n"[..], &mut Vec::new(), &opts).unwrap();
+    }
 }
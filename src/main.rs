@@ -1,61 +1,1005 @@
-use intelhexes::{hex2dump, hex2bin};
+use intelhexes::{
+    AddrRange, Algorithm, BinDataWriter, BinOptions, Comments, Compat, ConvertWarnings,
+    DumpOptions, Endian, HexOptions, InfoOptions, IntelHexDataWriter, LexMode, MissingEof, StampOptions,
+};
+use std::env;
+use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::result;
+use std::str::FromStr;
 use structopt::StructOpt;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    Always,
+    Never,
+    Auto,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<ColorChoice, String> {
+        match s {
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            "auto" => Ok(ColorChoice::Auto),
+            _ => Err(format!("Expected always, never or auto, found {:?}", s)),
+        }
+    }
+}
+
+/// Byte pattern given as hex digits, e.g. DEADBEEF or "de ad be ef"
+#[derive(Debug, Default)]
+struct HexBytes(Vec<u8>);
+
+impl FromStr for HexBytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<HexBytes, String> {
+        let digits: String = s.split_whitespace().collect();
+        let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+
+        if digits.is_empty() || !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+            return Err(format!("Expected an even number of hex digits, found {:?}", s));
+        }
+
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<result::Result<Vec<u8>, _>>()
+            .map(HexBytes)
+            .map_err(|e| format!("Invalid hex bytes {:?}: {}", s, e))
+    }
+}
+
+/// Parses a decimal or 0x prefixed hex number
+fn parse_u32(s: &str) -> result::Result<u32, String> {
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|e| format!("Invalid number {:?}: {}", s, e))
+}
+
 #[derive(StructOpt, Debug)]
-#[structopt(name = "basic")]
-struct Opt {
+#[structopt(name = "intelhexes", about = "Inspect and convert Intel HEX files")]
+enum Opt {
     /// Produce a human-readable dump from the intel HEX input file
-    #[structopt(long)]
-    hex2dump: bool,
+    #[structopt(alias = "hex2dump")]
+    Dump(DumpOpt),
 
     /// Produce a binary from the intel HEX input file
+    #[structopt(alias = "hex2bin")]
+    Bin(BinOpt),
+
+    /// Convert the intel HEX input file to hex, bin or dump, with the
+    /// same range and input checks for every format
+    Convert(ConvertOpt),
+
+    /// Compute a CRC or digest over the binary image of the intel HEX input
+    /// file, or over address ranges of it
+    Hash(HashOpt),
+
+    /// Write the intel HEX input file as hex or binary with a CRC or digest
+    /// over address ranges of it stamped at an address
+    Stamp(StampOpt),
+
+    /// Summarise the segments, records and layout of the intel HEX input file
+    Info(InfoOpt),
+
+    /// Check intel HEX files for malformed records and layout problems,
+    /// exits with 1 if any errors are found
+    Verify(VerifyOpt),
+}
+
+#[derive(StructOpt, Debug)]
+struct IoOpt {
+    /// Output file, created or truncated, stdout if unspecified or `-`
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Write the output to a temporary file next to it and rename it into
+    /// place once complete, so that it is never seen half written
+    #[structopt(long, requires = "output")]
+    atomic: bool,
+
+    /// Input file, stdin if unspecified or `-`
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: Option<PathBuf>,
+}
+
+impl IoOpt {
+    /// The input path, `None` for stdin
+    fn input(&self) -> Option<&Path> {
+        self.file.as_deref().filter(|&path| path != Path::new("-"))
+    }
+
+    /// The output path, `None` for stdout
+    fn output(&self) -> Option<&Path> {
+        self.output.as_deref().filter(|&path| path != Path::new("-"))
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct InfoOpt {
+    /// Check the Cortex-M vector table at this address: a Thumb reset
+    /// handler within the data and matching the start address, and an
+    /// initial SP pointing into RAM
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    check_vectors: Option<u32>,
+
+    /// RAM the initial SP may point into, START..END or START+LEN, may be
+    /// repeated. 0x20000000..0x40000000 if unspecified
+    #[structopt(long = "ram", number_of_values = 1, requires = "check-vectors")]
+    ram: Vec<AddrRange>,
+
+    /// Skip whitespace, comments and \x1A end of file markers between
+    /// records, printing how many lines held any
+    #[structopt(long)]
+    lenient: bool,
+
+    /// Comment prefixes skipped with --lenient, a comma separated list of
+    /// ;, # and //
+    #[structopt(long, default_value = ";,#,//")]
+    comments: Comments,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+/// The lexer mode of the `--lenient` and `--comments` options
+fn lex_mode(lenient: bool, comments: Comments) -> LexMode {
+    if lenient {
+        LexMode::Lenient(comments)
+    } else {
+        LexMode::Strict
+    }
+}
+
+/// Checks of the input made while converting it
+#[derive(StructOpt, Debug)]
+struct CheckOpt {
+    /// Fail on data records writing different bytes to an address written
+    /// before, warn about and skip those repeating the earlier bytes
+    #[structopt(long)]
+    check_overlaps: bool,
+
+    /// What to do when the input ends without an EndOfFile record: error,
+    /// warn or ignore, the last two convert the data read so far
+    #[structopt(long, default_value = "error")]
+    missing_eof: MissingEof,
+
+    /// Fail if anything but whitespace follows the EndOfFile record
     #[structopt(long)]
-    hex2bin: bool,
+    reject_data_after_eof: bool,
 
-    /// Byte used to fill empty address space when producing a binary
+    /// Skip whitespace, comments and \x1A end of file markers between
+    /// records, printing how many lines held any
+    #[structopt(long)]
+    lenient: bool,
+
+    /// Comment prefixes skipped with --lenient, a comma separated list of
+    /// ;, # and //
+    #[structopt(long, default_value = ";,#,//")]
+    comments: Comments,
+}
+
+impl CheckOpt {
+    fn lex_mode(&self) -> LexMode {
+        lex_mode(self.lenient, self.comments)
+    }
+
+    /// The options of a binary image with these checks
+    fn bin_options(&self, fill_byte: Option<u8>, ranges: Vec<AddrRange>) -> BinOptions {
+        BinOptions {
+            fill_byte: fill_byte.unwrap_or(0xff),
+            ranges,
+            check_overlaps: self.check_overlaps,
+            missing_eof: self.missing_eof,
+            reject_data_after_eof: self.reject_data_after_eof,
+            lex_mode: self.lex_mode(),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct DumpOpt {
+    /// Reproduce the dump output of another tool, possible values: python
+    #[structopt(long)]
+    compat: Option<Compat>,
+
+    /// Collapse consecutive identical dump rows into a single `*` line
+    #[structopt(short, long, conflicts_with = "compat")]
+    squeeze: bool,
+
+    /// Print segment and gap lines between contiguous regions of the dump,
+    /// followed by a summary footer
+    #[structopt(short, long, conflicts_with = "compat")]
+    annotate: bool,
+
+    /// Only output data within START..END or START+LEN, may be repeated
+    #[structopt(long = "range", number_of_values = 1)]
+    ranges: Vec<AddrRange>,
+
+    /// Colour the dump by byte class: always, never or auto, where auto
+    /// colours only when writing to a terminal, the default
+    #[structopt(long, conflicts_with = "compat")]
+    color: Option<ColorChoice>,
+
+    /// Highlight all occurrences of a byte pattern given in hex, e.g. DEADBEEF
+    #[structopt(long)]
+    highlight: Option<HexBytes>,
+
+    /// Highlight the data within START..END or START+LEN, may be repeated
+    #[structopt(long = "highlight-range", number_of_values = 1)]
+    highlight_ranges: Vec<AddrRange>,
+
+    #[structopt(flatten)]
+    checks: CheckOpt,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+#[derive(StructOpt, Debug)]
+struct BinOpt {
+    /// Byte used to fill empty address space
     #[structopt(long)]
     fill_byte: Option<u8>,
 
-    /// Output file, stdout if unspecified
-    #[structopt(short, long, parse(from_os_str))]
-    output: Option<PathBuf>,
+    /// Only output data within START..END or START+LEN, may be repeated
+    #[structopt(long = "range", number_of_values = 1)]
+    ranges: Vec<AddrRange>,
+
+    #[structopt(flatten)]
+    checks: CheckOpt,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+/// Output formats of `convert`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConvertFormat {
+    Hex,
+    Bin,
+    Dump,
+}
+
+impl FromStr for ConvertFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<ConvertFormat, String> {
+        match s {
+            "hex" => Ok(ConvertFormat::Hex),
+            "bin" => Ok(ConvertFormat::Bin),
+            "dump" => Ok(ConvertFormat::Dump),
+            _ => Err(format!("Expected hex, bin or dump, found {:?}", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct ConvertOpt {
+    /// Output format: hex, rewritten in rows of 16 bytes, bin, or dump in
+    /// the plain layout of the dump subcommand
+    #[structopt(long)]
+    to: ConvertFormat,
+
+    /// Byte used to fill empty address space of a binary
+    #[structopt(long)]
+    fill_byte: Option<u8>,
+
+    /// Only output data within START..END or START+LEN, may be repeated
+    #[structopt(long = "range", number_of_values = 1)]
+    ranges: Vec<AddrRange>,
 
-    /// Input file
+    #[structopt(flatten)]
+    checks: CheckOpt,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+/// The CRC or digest computed by `hash` and `stamp`
+#[derive(StructOpt, Debug)]
+struct DigestOpt {
+    /// crc16-ccitt (CCITT-FALSE), crc32, sha256 or md5
+    #[structopt(short, long, default_value = "crc32")]
+    algorithm: Algorithm,
+
+    /// CRC polynomial in normal form, replacing the algorithm's
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    poly: Option<u32>,
+
+    /// Initial CRC register value, replacing the algorithm's
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    init: Option<u32>,
+
+    /// Whether the CRC is reflected, true or false, replacing the
+    /// algorithm's
+    #[structopt(long)]
+    reflect: Option<bool>,
+
+    /// Value xored into the final CRC, replacing the algorithm's
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    xor_out: Option<u32>,
+}
+
+impl DigestOpt {
+    /// The algorithm with the CRC parameters given replaced
+    fn algorithm(&self) -> io::Result<Algorithm> {
+        let overrides = self.poly.is_some()
+            || self.init.is_some()
+            || self.reflect.is_some()
+            || self.xor_out.is_some();
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        match self.algorithm {
+            Algorithm::Crc(params) => {
+                let mask = u32::MAX >> (32 - params.width);
+                let check = |name: &str, value: Option<u32>, default: u32| match value {
+                    Some(v) if v & !mask != 0 => Err(invalid(format!(
+                        "--{} {:#x} does not fit a {}-bit CRC",
+                        name, v, params.width
+                    ))),
+                    Some(v) => Ok(v),
+                    None => Ok(default),
+                };
+
+                Ok(Algorithm::Crc(intelhexes::CrcParams {
+                    poly: check("poly", self.poly, params.poly)?,
+                    init: check("init", self.init, params.init)?,
+                    reflect: self.reflect.unwrap_or(params.reflect),
+                    xor_out: check("xor-out", self.xor_out, params.xor_out)?,
+                    ..params
+                }))
+            }
+            _ if overrides => Err(invalid(
+                "--poly, --init, --reflect and --xor-out only apply to CRCs".into(),
+            )),
+            algorithm => Ok(algorithm),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct HashOpt {
+    #[structopt(flatten)]
+    digest: DigestOpt,
+
+    /// Byte used to fill empty address space
+    #[structopt(long)]
+    fill_byte: Option<u8>,
+
+    /// Hash START..END or START+LEN in full, may be repeated in increasing
+    /// address order
+    #[structopt(long = "range", number_of_values = 1)]
+    ranges: Vec<AddrRange>,
+
+    #[structopt(flatten)]
+    checks: CheckOpt,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Hex,
+    Bin,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<ImageFormat, String> {
+        match s {
+            "hex" => Ok(ImageFormat::Hex),
+            "bin" => Ok(ImageFormat::Bin),
+            _ => Err(format!("Expected hex or bin, found {:?}", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct StampOpt {
+    #[structopt(flatten)]
+    digest: DigestOpt,
+
+    /// Address the CRC or digest is written at, replacing any data there
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    at: u32,
+
+    /// Byte order of a CRC: little or big, digests are written as they are
+    #[structopt(long, default_value = "little")]
+    endian: Endian,
+
+    /// Output format: hex or bin, gaps in a binary filled with --fill-byte
+    #[structopt(long, default_value = "hex")]
+    format: ImageFormat,
+
+    /// Byte used to fill empty address space
+    #[structopt(long)]
+    fill_byte: Option<u8>,
+
+    /// Hash START..END or START+LEN in full, may be repeated in increasing
+    /// address order
+    #[structopt(long = "range", number_of_values = 1, required = true)]
+    ranges: Vec<AddrRange>,
+
+    #[structopt(flatten)]
+    checks: CheckOpt,
+
+    #[structopt(flatten)]
+    io: IoOpt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Expected text or json, found {:?}", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct VerifyOpt {
+    /// Report format: text, one `FILE:LINE: severity: message` line per
+    /// problem, or json
+    #[structopt(long, default_value = "text")]
+    format: Format,
+
+    /// Input files, stdin if unspecified or `-`
     #[structopt(name = "FILE", parse(from_os_str))]
-    file: PathBuf,
+    files: Vec<PathBuf>,
 }
 
-fn main() {
-    let opt = Opt::from_args();
-    let output_file: Box<dyn io::Write> = opt
-        .output
-        .map(fs::File::open)
-        .map(result::Result::ok)
-        .flatten()
-        .map(|f| Box::new(f) as Box<dyn io::Write>)
-        .unwrap_or(Box::new(io::stdout()));
-
-    let input_file = fs::File::open(opt.file).expect("Invalid input file path");
-
-    let exit_code = if opt.hex2dump {
-        match hex2dump(input_file, output_file) {
-            Ok(_) => 0,
-            Err(e) => e.raw_os_error().unwrap_or(1),
-        }
-    } else if opt.hex2bin {
-        let fill_byte = opt.fill_byte.unwrap_or(0xff);
-        match hex2bin(input_file, output_file, fill_byte) {
-            Ok(_) => 0,
-            Err(e) => e.raw_os_error().unwrap_or(1),
+/// Rewrites the `--hex2dump` and `--hex2bin` flags of earlier versions,
+/// which could be given anywhere, into the matching subcommand
+fn legacy_args<I: IntoIterator<Item = OsString>>(args: I) -> result::Result<Vec<OsString>, String> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let flags: Vec<usize> = (1..args.len())
+        .filter(|&i| args[i] == "--hex2dump" || args[i] == "--hex2bin")
+        .collect();
+
+    match flags[..] {
+        [] => {}
+        [i] => {
+            let cmd = if args[i] == "--hex2dump" { "dump" } else { "bin" };
+            args.remove(i);
+            args.insert(1, cmd.into());
         }
-    } else {
-        println!("No operations specified, bye!");
-        0
+        _ => return Err("Only one of --hex2dump and --hex2bin may be given".into()),
+    }
+
+    Ok(args)
+}
+
+/// Maps the input file into memory so that it is parsed in place.
+///
+/// This is only sound as long as no other process modifies or truncates the
+/// file while it is being converted.
+#[cfg(feature = "mmap")]
+fn map_input(file: &fs::File) -> io::Result<memmap2::Mmap> {
+    unsafe { memmap2::Mmap::map(file) }
+}
+
+fn open_file(path: &Path) -> io::Result<fs::File> {
+    fs::File::open(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// Opens the input file, or stdin
+fn open_input(input: Option<&Path>) -> io::Result<Box<dyn io::Read>> {
+    match input {
+        Some(path) => Ok(Box::new(open_file(path)?)),
+        None => Ok(Box::new(io::stdin().lock())),
+    }
+}
+
+/// Output format and its options
+enum Conversion {
+    Dump(DumpOptions),
+    Bin(BinOptions),
+    Hex(HexOptions),
+}
+
+impl Conversion {
+    #[cfg(not(feature = "parallel"))]
+    fn convert<R: io::Read, W: io::Write>(&self, reader: R, writer: W) -> io::Result<ConvertWarnings> {
+        match self {
+            Conversion::Dump(opts) => intelhexes::hex2dump_with(reader, writer, opts),
+            Conversion::Bin(opts) => intelhexes::hex2bin_with(reader, writer, opts),
+            Conversion::Hex(opts) => intelhexes::hex2hex_with(reader, writer, opts),
+        }
+    }
+
+    #[cfg(all(feature = "mmap", not(feature = "parallel")))]
+    fn convert_slice<W: io::Write>(&self, input: &[u8], writer: W) -> io::Result<ConvertWarnings> {
+        match self {
+            Conversion::Dump(opts) => intelhexes::hex2dump_slice(input, writer, opts),
+            Conversion::Bin(opts) => intelhexes::hex2bin_slice(input, writer, opts),
+            Conversion::Hex(opts) => intelhexes::hex2hex_slice(input, writer, opts),
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn convert_parallel<W: io::Write + Send>(&self, input: &[u8], writer: W) -> io::Result<ConvertWarnings> {
+        match self {
+            Conversion::Dump(opts) => intelhexes::hex2dump_parallel(input, writer, opts),
+            Conversion::Bin(opts) => intelhexes::hex2bin_parallel(input, writer, opts),
+            Conversion::Hex(opts) => intelhexes::hex2hex_parallel(input, writer, opts),
+        }
+    }
+
+    /// Converts the input read from `reader`, which is read into memory
+    /// first for the parallel conversion
+    fn convert_reader<R: io::Read, W: io::Write + Send>(&self, reader: R, writer: W) -> io::Result<ConvertWarnings> {
+        #[cfg(feature = "parallel")]
+        {
+            let mut reader = reader;
+            let mut input = Vec::new();
+            reader.read_to_end(&mut input)?;
+            self.convert_parallel(&input, writer)
+        }
+        #[cfg(not(feature = "parallel"))]
+        self.convert(reader, writer)
+    }
+
+    /// Converts the input to the output selected by `io_opt`, then prints
+    /// the warnings
+    fn write(&self, io_opt: &IoOpt) -> io::Result<()> {
+        let mut warnings = ConvertWarnings::default();
+        with_output(io_opt, |writer| {
+            warnings = self.convert_input(io_opt.input(), writer)?;
+            Ok(())
+        })?;
+        print_warnings(&warnings);
+        Ok(())
+    }
+
+    /// Converts the input file, or stdin, by the fastest means the enabled
+    /// features allow
+    fn convert_input<W: io::Write + Send>(&self, input: Option<&Path>, writer: W) -> io::Result<ConvertWarnings> {
+        let path = match input {
+            Some(path) => path,
+            None => return self.convert_reader(io::stdin().lock(), writer),
+        };
+        let file = open_file(path)?;
+
+        #[cfg(all(feature = "mmap", feature = "parallel"))]
+        {
+            self.convert_parallel(&map_input(&file)?, writer)
+        }
+        #[cfg(all(feature = "mmap", not(feature = "parallel")))]
+        {
+            self.convert_slice(&map_input(&file)?, writer)
+        }
+        #[cfg(not(feature = "mmap"))]
+        self.convert_reader(file, writer)
+    }
+}
+
+/// Prints what a conversion warned about to stderr
+fn print_warnings(warnings: &ConvertWarnings) {
+    for problem in &warnings.overlaps {
+        eprintln!("warning: {}", problem);
+    }
+    if let Some(e) = warnings.missing_eof {
+        eprintln!("warning: {}", e);
+    }
+    if warnings.skipped_lines > 0 {
+        eprintln!(
+            "warning: skipped comments, whitespace or end of file markers on {} line{}",
+            warnings.skipped_lines,
+            if warnings.skipped_lines == 1 { "" } else { "s" }
+        );
+    }
+}
+
+/// Hands the output selected by `io_opt` to `write`, wrapped in a
+/// `BufWriter`
+fn with_output<F>(io_opt: &IoOpt, write: F) -> io::Result<()>
+where
+    F: FnOnce(io::BufWriter<Box<dyn io::Write + Send + '_>>) -> io::Result<()>,
+{
+    let path = match io_opt.output() {
+        Some(path) => path,
+        None => return write(io::BufWriter::new(Box::new(io::stdout()))),
+    };
+
+    if let (Some(input), Ok(output)) = (io_opt.input(), fs::canonicalize(path)) {
+        if fs::canonicalize(input).is_ok_and(|input| input == output) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Refusing to overwrite the input file {}", path.display()),
+            ));
+        }
+    }
+
+    let open = |path: &Path| {
+        fs::File::create(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    };
+
+    if !io_opt.atomic {
+        return write(io::BufWriter::new(Box::new(open(path)?)));
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+
+    let file = open(&temp)?;
+    let res = write(io::BufWriter::new(Box::new(&file)))
+        .and_then(|_| file.sync_all())
+        .and_then(|_| fs::rename(&temp, path));
+    if res.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+
+    res
+}
+
+fn dump(opt: DumpOpt) -> io::Result<()> {
+    let io = opt.io;
+    let color = match opt.color.unwrap_or(ColorChoice::Auto) {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            io.output().is_none() && io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+        }
+    };
+
+    let conversion = Conversion::Dump(DumpOptions {
+        compat: opt.compat,
+        squeeze: opt.squeeze,
+        annotate: opt.annotate,
+        ranges: opt.ranges,
+        color,
+        highlight_bytes: opt.highlight.unwrap_or_default().0,
+        highlight_ranges: opt.highlight_ranges,
+        check_overlaps: opt.checks.check_overlaps,
+        missing_eof: opt.checks.missing_eof,
+        reject_data_after_eof: opt.checks.reject_data_after_eof,
+        lex_mode: opt.checks.lex_mode(),
+    });
+
+    conversion.write(&io)
+}
+
+fn bin(opt: BinOpt) -> io::Result<()> {
+    let io = opt.io;
+    let conversion = Conversion::Bin(opt.checks.bin_options(opt.fill_byte, opt.ranges));
+
+    conversion.write(&io)
+}
+
+fn convert(opt: ConvertOpt) -> io::Result<()> {
+    let io = opt.io;
+    let checks = opt.checks;
+    if opt.fill_byte.is_some() && opt.to != ConvertFormat::Bin {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--fill-byte only applies to --to bin",
+        ));
+    }
+    let conversion = match opt.to {
+        ConvertFormat::Hex => Conversion::Hex(HexOptions {
+            ranges: opt.ranges,
+            check_overlaps: checks.check_overlaps,
+            missing_eof: checks.missing_eof,
+            reject_data_after_eof: checks.reject_data_after_eof,
+            lex_mode: checks.lex_mode(),
+        }),
+        ConvertFormat::Bin => Conversion::Bin(checks.bin_options(opt.fill_byte, opt.ranges)),
+        ConvertFormat::Dump => Conversion::Dump(DumpOptions {
+            ranges: opt.ranges,
+            check_overlaps: checks.check_overlaps,
+            missing_eof: checks.missing_eof,
+            reject_data_after_eof: checks.reject_data_after_eof,
+            lex_mode: checks.lex_mode(),
+            ..DumpOptions::default()
+        }),
+    };
+
+    conversion.write(&io)
+}
+
+fn hash(opt: HashOpt) -> io::Result<()> {
+    let algorithm = opt.digest.algorithm()?;
+    let io = opt.io;
+    let opts = opt.checks.bin_options(opt.fill_byte, opt.ranges);
+
+    let (digest, warnings) = intelhexes::hash(open_input(io.input())?, algorithm, &opts)?;
+    print_warnings(&warnings);
+    let digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    let name = io.input().map_or("-".into(), |path| path.display().to_string());
+
+    with_output(&io, |mut writer| {
+        writeln!(writer, "{}  {}", digest, name)?;
+        writer.flush()
+    })
+}
+
+fn stamp(opt: StampOpt) -> io::Result<()> {
+    let io = opt.io;
+    let opts = StampOptions {
+        algorithm: opt.digest.algorithm()?,
+        hash: opt.checks.bin_options(opt.fill_byte, opt.ranges),
+        addr: opt.at,
+        endian: opt.endian,
     };
+    let format = opt.format;
+    let input = open_input(io.input())?;
+
+    let mut warnings = ConvertWarnings::default();
+    with_output(&io, |writer| {
+        let (_, stamp_warnings) = match format {
+            ImageFormat::Hex => intelhexes::stamp(input, writer, &mut IntelHexDataWriter::new(), &opts),
+            ImageFormat::Bin => {
+                let mut bin = BinDataWriter::new(opts.hash.fill_byte);
+                intelhexes::stamp(input, writer, &mut bin, &opts)
+            }
+        }?;
+        warnings = stamp_warnings;
+        Ok(())
+    })?;
+    print_warnings(&warnings);
+    Ok(())
+}
+
+fn info(opt: InfoOpt) -> io::Result<()> {
+    let io = opt.io;
+    let opts = InfoOptions {
+        check_vectors: opt.check_vectors,
+        ram: opt.ram,
+        lex_mode: lex_mode(opt.lenient, opt.comments),
+    };
+    let info = intelhexes::info_with(open_input(io.input())?, &opts)?;
+
+    with_output(&io, |mut writer| {
+        write!(writer, "{}", info)?;
+        writer.flush()
+    })
+}
+
+/// Quotes and escapes `s` as a JSON string
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn verify(opt: VerifyOpt) -> io::Result<i32> {
+    let files = if opt.files.is_empty() { vec![PathBuf::from("-")] } else { opt.files };
+    let mut stdout = io::stdout().lock();
+    let mut failed = false;
+
+    if opt.format == Format::Json {
+        write!(stdout, "[")?;
+    }
+    for (i, file) in files.iter().enumerate() {
+        let res = match Some(file.as_path()).filter(|&path| path != Path::new("-")) {
+            Some(path) => fs::File::open(path).and_then(intelhexes::verify),
+            None => intelhexes::verify(io::stdin().lock()),
+        };
+        /* A file that can not be read is reported like its problems, and the
+         * other files are still verified */
+        let report = match res {
+            Ok(report) => report,
+            Err(e) => {
+                failed = true;
+                match opt.format {
+                    Format::Text => writeln!(stdout, "{}: error: {}", file.display(), e)?,
+                    Format::Json => write!(
+                        stdout,
+                        "{}{{\"file\":{},\"error\":{}}}",
+                        if i == 0 { "" } else { "," },
+                        json_string(&file.to_string_lossy()),
+                        json_string(&e.to_string())
+                    )?,
+                }
+                continue;
+            }
+        };
+        failed |= report.errors() > 0;
+
+        match opt.format {
+            Format::Text => {
+                for problem in &report.problems {
+                    writeln!(
+                        stdout,
+                        "{}:{}: {}: {}",
+                        file.display(),
+                        problem.line,
+                        problem.severity(),
+                        problem.issue
+                    )?;
+                }
+                writeln!(
+                    stdout,
+                    "{}: {} errors, {} warnings",
+                    file.display(),
+                    report.errors(),
+                    report.warnings()
+                )?;
+            }
+            Format::Json => {
+                let problems: Vec<String> = report
+                    .problems
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{{\"line\":{},\"severity\":\"{}\",\"message\":{}}}",
+                            p.line,
+                            p.severity(),
+                            json_string(&p.issue.to_string())
+                        )
+                    })
+                    .collect();
+                write!(
+                    stdout,
+                    "{}{{\"file\":{},\"errors\":{},\"warnings\":{},\"problems\":[{}]}}",
+                    if i == 0 { "" } else { "," },
+                    json_string(&file.to_string_lossy()),
+                    report.errors(),
+                    report.warnings(),
+                    problems.join(",")
+                )?;
+            }
+        }
+    }
+    if opt.format == Format::Json {
+        writeln!(stdout, "]")?;
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
+
+fn main() {
+    let args = legacy_args(env::args_os()).unwrap_or_else(|e| {
+        structopt::clap::Error::with_description(&e, structopt::clap::ErrorKind::ArgumentConflict).exit()
+    });
+
+    let res = match Opt::from_iter(args) {
+        Opt::Dump(opt) => dump(opt).map(|_| 0),
+        Opt::Bin(opt) => bin(opt).map(|_| 0),
+        Opt::Convert(opt) => convert(opt).map(|_| 0),
+        Opt::Hash(opt) => hash(opt).map(|_| 0),
+        Opt::Stamp(opt) => stamp(opt).map(|_| 0),
+        Opt::Info(opt) => info(opt).map(|_| 0),
+        Opt::Verify(opt) => verify(opt),
+    };
+
+    std::process::exit(exit_code(res));
+}
+
+/// Reports an error and turns the result into the exit status
+fn exit_code(res: io::Result<i32>) -> i32 {
+    match res {
+        Ok(code) => code,
+        /* A reader like `head` closing the pipe early is not a failure */
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn it_turns_legacy_flags_into_subcommands() {
+        assert_eq!(
+            legacy_args(args(&["intelhexes", "--hex2dump", "-s", "image.hex"])),
+            Ok(args(&["intelhexes", "dump", "-s", "image.hex"]))
+        );
+        assert_eq!(
+            legacy_args(args(&["intelhexes", "--fill-byte", "0", "image.hex", "--hex2bin"])),
+            Ok(args(&["intelhexes", "bin", "--fill-byte", "0", "image.hex"]))
+        );
+        assert_eq!(
+            legacy_args(args(&["intelhexes", "dump", "image.hex"])),
+            Ok(args(&["intelhexes", "dump", "image.hex"]))
+        );
+        assert!(legacy_args(args(&["intelhexes", "--hex2dump", "--hex2bin", "image.hex"])).is_err());
+    }
+
+    #[test]
+    fn it_requires_a_subcommand() {
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "image.hex"])).is_err());
+        assert!(Opt::from_iter_safe(args(&["intelhexes"])).is_err());
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "bin", "--squeeze", "image.hex"])).is_err());
+        assert!(matches!(
+            Opt::from_iter_safe(args(&["intelhexes", "hex2bin", "image.hex"])),
+            Ok(Opt::Bin(_))
+        ));
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "dump", "--compat", "python", "image.hex"])).is_ok());
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "dump", "--compat", "python", "--color", "never", "image.hex"])).is_err());
+        assert!(matches!(
+            Opt::from_iter_safe(args(&["intelhexes", "convert", "--to", "dump", "image.hex"])),
+            Ok(Opt::Convert(ConvertOpt { to: ConvertFormat::Dump, .. }))
+        ));
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "convert", "image.hex"])).is_err());
+        assert!(Opt::from_iter_safe(args(&["intelhexes", "convert", "--to", "elf", "image.hex"])).is_err());
+    }
+
+    #[test]
+    fn it_escapes_json_strings() {
+        assert_eq!(json_string("a \"b\" \\ c\n"), r#""a \"b\" \\ c\u000a""#);
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("intelhexes-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn it_refuses_to_overwrite_the_input() {
+        let path = temp_path("input.hex");
+        fs::write(&path, ":00000001FF\n").unwrap();
+        let io_opt = IoOpt { output: Some(path.clone()), atomic: false, file: Some(path.clone()) };
+
+        let res = with_output(&io_opt, |mut writer| io::Write::write_all(&mut writer, b"output"));
+
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&path).unwrap(), b":00000001FF\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn it_renames_atomic_output_into_place() {
+        let path = temp_path("atomic.bin");
+        let io_opt = IoOpt { output: Some(path.clone()), atomic: true, file: None };
+
+        let res = with_output(&io_opt, |mut writer| {
+            io::Write::write_all(&mut writer, b"partial")?;
+            Err(io::Error::other("conversion failed"))
+        });
+
+        assert!(res.is_err());
+        assert!(!path.exists());
+        assert!(!path.with_extension(format!("bin.{}.tmp", std::process::id())).exists());
+
+        with_output(&io_opt, |mut writer| io::Write::write_all(&mut writer, b"complete")).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"complete");
+        fs::remove_file(&path).unwrap();
+    }
 
-    std::process::exit(exit_code);
+    #[test]
+    fn it_exits_quietly_on_a_closed_pipe() {
+        assert_eq!(exit_code(Err(io::ErrorKind::BrokenPipe.into())), 0);
+        assert_eq!(exit_code(Err(io::Error::from_raw_os_error(2))), 1);
+        assert_eq!(exit_code(Ok(1)), 1);
+    }
 }
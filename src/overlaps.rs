@@ -0,0 +1,217 @@
+use std::cmp;
+use std::collections::BTreeMap;
+
+/// Two data records writing the same addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlap {
+    /// First address written twice
+    pub start: u32,
+    /// Address following the last one written twice
+    pub end: u64,
+    /// Line of the record writing the addresses again
+    pub line: usize,
+    /// Line of the record that wrote them first
+    pub other_line: usize,
+    /// Both records hold the same bytes at these addresses
+    pub identical: bool,
+}
+
+/// Contiguous data, with the lines of the records that wrote it
+#[derive(Default)]
+struct Block {
+    data: Vec<u8>,
+    /// (offset, line), the bytes from offset up to the next entry were
+    /// written by the record on line
+    lines: Vec<(usize, usize)>,
+}
+
+impl Block {
+    fn push(&mut self, line: usize, bytes: &[u8]) {
+        if self.lines.last().map(|&(_, l)| l) != Some(line) {
+            self.lines.push((self.data.len(), line));
+        }
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn append(&mut self, other: Block) {
+        let offset = self.data.len();
+        for (pos, line) in other.lines {
+            if self.lines.last().map(|&(_, l)| l) != Some(line) {
+                self.lines.push((offset + pos, line));
+            }
+        }
+        self.data.extend(other.data);
+    }
+
+    /// Compares the block starting at `start` to `bytes` at `addr`, for
+    /// every record the two overlap with
+    fn find_overlaps(
+        &self,
+        start: u64,
+        line: usize,
+        addr: u64,
+        bytes: &[u8],
+        overlaps: &mut Vec<Overlap>,
+    ) {
+        let from = cmp::max(start, addr) - start;
+        let to = cmp::min(start + self.data.len() as u64, addr + bytes.len() as u64) - start;
+        if from >= to {
+            return;
+        }
+        let (from, to) = (from as usize, to as usize);
+
+        let first = self.lines.partition_point(|&(pos, _)| pos <= from) - 1;
+        for (i, &(pos, other_line)) in self.lines.iter().enumerate().skip(first) {
+            if pos >= to {
+                break;
+            }
+            let next = self
+                .lines
+                .get(i + 1)
+                .map_or(self.data.len(), |&(pos, _)| pos);
+            let (lo, hi) = (cmp::max(pos, from), cmp::min(next, to));
+            let new = (start + lo as u64 - addr) as usize;
+
+            overlaps.push(Overlap {
+                start: (start + lo as u64) as u32,
+                end: start + hi as u64,
+                line,
+                other_line,
+                identical: self.data[lo..hi] == bytes[new..new + hi - lo],
+            });
+        }
+    }
+}
+
+/// Remembers the data written so far to find records writing the same
+/// addresses again. Where data is written twice the first record's bytes
+/// are kept, so later records are always compared to those.
+#[derive(Default)]
+pub struct OverlapChecker {
+    /// Non-adjacent blocks of data by start address
+    blocks: BTreeMap<u64, Block>,
+}
+
+impl OverlapChecker {
+    pub fn new() -> OverlapChecker {
+        OverlapChecker::default()
+    }
+
+    /// Records the data written at `addr` by the record on `line` and adds
+    /// its overlaps with earlier data to `overlaps`, ordered by address
+    pub fn write(&mut self, line: usize, addr: u32, bytes: &[u8], overlaps: &mut Vec<Overlap>) {
+        if bytes.is_empty() {
+            return;
+        }
+        let (start, end) = (addr as u64, addr as u64 + bytes.len() as u64);
+
+        /* Blocks overlapping or adjacent to the data are merged with it */
+        let mut touching: Vec<u64> = self
+            .blocks
+            .range(..=end)
+            .rev()
+            .take_while(|(&s, block)| s + block.data.len() as u64 >= start)
+            .map(|(&s, _)| s)
+            .collect();
+        touching.reverse();
+
+        let mut merged: Option<(u64, Block)> = None;
+        /* First address of the data not yet placed in the merged block */
+        let mut pos = start;
+        for s in touching {
+            let block = self.blocks.remove(&s).unwrap();
+            block.find_overlaps(s, line, start, bytes, overlaps);
+            let block_end = s + block.data.len() as u64;
+
+            match merged {
+                None if s <= start => merged = Some((s, block)),
+                _ => {
+                    let (_, m) = merged.get_or_insert_with(|| (start, Block::default()));
+                    if pos < s {
+                        m.push(line, &bytes[(pos - start) as usize..(s - start) as usize]);
+                    }
+                    m.append(block);
+                }
+            }
+            pos = cmp::max(pos, block_end);
+        }
+
+        let (s, mut m) = merged.unwrap_or((start, Block::default()));
+        if pos < end {
+            m.push(line, &bytes[(pos - start) as usize..]);
+        }
+        self.blocks.insert(s, m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::overlaps::*;
+
+    fn write(checker: &mut OverlapChecker, line: usize, addr: u32, bytes: &[u8]) -> Vec<Overlap> {
+        let mut overlaps = Vec::new();
+        checker.write(line, addr, bytes, &mut overlaps);
+        overlaps
+    }
+
+    #[test]
+    fn it_finds_no_overlaps_in_adjacent_data() {
+        let mut checker = OverlapChecker::new();
+
+        assert!(write(&mut checker, 1, 0x10, &[1, 2]).is_empty());
+        assert!(write(&mut checker, 2, 0x12, &[3, 4]).is_empty());
+        assert!(write(&mut checker, 3, 0x0e, &[5, 6]).is_empty());
+        assert!(write(&mut checker, 4, 0x20, &[7]).is_empty());
+        assert!(write(&mut checker, 5, 0x14, &[0; 12]).is_empty());
+        assert_eq!(checker.blocks.len(), 1);
+    }
+
+    #[test]
+    fn it_compares_with_every_record_overlapped() {
+        let mut checker = OverlapChecker::new();
+        write(&mut checker, 1, 0x10, &[1, 2, 3, 4]);
+        write(&mut checker, 2, 0x14, &[5, 6]);
+        write(&mut checker, 3, 0x20, &[9]);
+
+        let overlaps = write(
+            &mut checker,
+            4,
+            0x12,
+            &[3, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9],
+        );
+
+        let found: Vec<(u32, u64, usize, bool)> = overlaps
+            .iter()
+            .map(|o| (o.start, o.end, o.other_line, o.identical))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0x12, 0x14, 1, false),
+                (0x14, 0x16, 2, true),
+                (0x20, 0x21, 3, true)
+            ]
+        );
+        assert!(overlaps.iter().all(|o| o.line == 4));
+    }
+
+    #[test]
+    fn it_keeps_the_first_bytes_written() {
+        let mut checker = OverlapChecker::new();
+        write(&mut checker, 1, 0x100, &[0xaa]);
+        write(&mut checker, 2, 0x100, &[0xbb]);
+
+        let overlaps = write(&mut checker, 3, 0xff, &[0, 0xaa]);
+
+        assert_eq!(
+            overlaps,
+            vec![Overlap {
+                start: 0x100,
+                end: 0x101,
+                line: 3,
+                other_line: 1,
+                identical: true
+            }]
+        );
+    }
+}
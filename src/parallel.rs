@@ -0,0 +1,450 @@
+use std::cmp;
+use std::io::{BufWriter, Result, Write};
+
+use rayon::prelude::*;
+
+use crate::chunks::AddressResolver;
+use crate::datawriter::DataWriter;
+use crate::helpers::hex_to_u8;
+use crate::pipeline::{BinOptions, Checks, ConvertWarnings, Converter, DumpOptions, HexOptions, WithDataWriter};
+use crate::record::{Comments, LexMode, LineError, Record, RecordError, Skip, SliceRecords, START_CODE};
+
+/// Input bytes decoded by one task, the input is split after the first
+/// newline following every `CHUNK_SZ` bytes
+const CHUNK_SZ: usize = 256 * 1024;
+
+/// A piece of the input ending after a newline, or at the end of the input
+struct Chunk {
+    start: usize,
+    end: usize,
+    /// Newlines in the chunk, records on later lines belong to the next one
+    newlines: usize,
+    /// Line number of the first line in the chunk
+    line: usize,
+    /// Address base in effect at the start of the chunk
+    resolver: AddressResolver,
+}
+
+enum Item {
+    /// `len` bytes at `addr` from the record on `line`, following the bytes
+    /// of the previous data item in `Batch::bytes`
+    Data { addr: u32, len: u8, line: usize },
+    /// Any record but a data record, and its line
+    Record(Box<Record>, usize),
+}
+
+/// The decoded records of one chunk, in order
+#[derive(Default)]
+struct Batch {
+    bytes: Vec<u8>,
+    items: Vec<Item>,
+    /// Decoding stopped at this error
+    error: Option<LineError>,
+    /// Data following the EndOfFile record, if the chunk holds it and it is
+    /// checked for
+    after_end: Option<LineError>,
+    /// Lines of the chunk on which the lenient lexer skipped something
+    warnings: usize,
+}
+
+fn split(input: &[u8], chunk_sz: usize) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::with_capacity(input.len() / chunk_sz + 1);
+    let mut start = 0;
+
+    loop {
+        let mut end = cmp::min(start + chunk_sz, input.len());
+        end = match input[end..].iter().position(|&c| c == b'\n') {
+            Some(pos) => end + pos + 1,
+            None => input.len(),
+        };
+        chunks.push((start, end));
+
+        if end == input.len() {
+            return chunks;
+        }
+        start = end;
+    }
+}
+
+/// True if a comment starts somewhere in `text`
+fn has_comment(comments: &Comments, text: &[u8]) -> bool {
+    (0..text.len()).any(|i| comments.skip(&text[i..]) == Skip::Line)
+}
+
+/// Counts the newlines in `input[start..end]` and finds the extended address
+/// records starting there, without validating them.
+///
+/// Valid data never contains a ':', so every one starts a record unless it is
+/// in a comment. Should the pre-scan be misled by a malformed record, decoding
+/// stops with an error at that record before the wrong base is used.
+fn prescan(input: &[u8], start: usize, end: usize, mode: LexMode) -> (usize, Option<AddressResolver>) {
+    let newlines = input[start..end].iter().filter(|&&c| c == b'\n').count();
+    let mut resolver = None;
+
+    for (pos, _) in input[start..end].iter().enumerate().filter(|(_, &c)| c == START_CODE) {
+        /* Comments run to the end of the line and can not be part of a record */
+        if let LexMode::Lenient(comments) = mode {
+            let line = &input[start..start + pos];
+            let line_start = line.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+            if has_comment(&comments, &line[line_start..]) {
+                continue;
+            }
+        }
+
+        let header = match input.get(start + pos..start + pos + 13) {
+            Some(header) => header,
+            None => continue,
+        };
+        if &header[1..3] != b"02" {
+            continue;
+        }
+
+        let value = match (hex_to_u8(&header[9..11]), hex_to_u8(&header[11..13])) {
+            (Some(hi), Some(lo)) => u16::from_be_bytes([hi, lo]),
+            _ => continue,
+        };
+        let record = match &header[7..9] {
+            b"02" => Record::ExtendedSegmentAddr(value),
+            b"04" => Record::ExtendedLinearAddr(value),
+            _ => continue,
+        };
+
+        resolver.get_or_insert_with(AddressResolver::new).apply(&record);
+    }
+
+    (newlines, resolver)
+}
+
+/// Decodes the records starting in `chunk`. The last record may run into the
+/// next chunk, exactly as when the input is parsed sequentially.
+fn decode(input: &[u8], chunk: &Chunk, checks: Checks) -> Batch {
+    let is_last = chunk.end == input.len();
+    let mut resolver = chunk.resolver;
+    let mut batch = Batch::default();
+
+    let mut records = SliceRecords::with_mode(&input[chunk.start..], checks.lex_mode);
+    /* Lines past the chunk are counted by the next one */
+    if !is_last {
+        records.warnings.until_line = Some(chunk.newlines);
+    }
+    while let Some(res) = records.next() {
+        let line = match res {
+            Ok((line, _)) => line,
+            Err(e) => e.line,
+        };
+        if line > chunk.newlines && !is_last {
+            break;
+        }
+
+        match res {
+            Ok((line, Record::Data { addr, bytes })) => {
+                let line = chunk.line + line - 1;
+                for (addr, piece) in resolver.resolve(addr, &bytes) {
+                    batch.bytes.extend_from_slice(piece);
+                    batch.items.push(Item::Data { addr, len: piece.len() as u8, line });
+                }
+            }
+            Ok((line, record)) => {
+                resolver.apply(&record);
+                batch.items.push(Item::Record(Box::new(record), chunk.line + line - 1));
+
+                if record == Record::EndOfFile && checks.data_after_eof {
+                    batch.after_end = records
+                        .check_end()
+                        .err()
+                        .map(|e| LineError { line: chunk.line + e.line - 1, ..e });
+                }
+            }
+            Err(e) => {
+                batch.error = Some(LineError { line: chunk.line + e.line - 1, ..e });
+            }
+        }
+    }
+    batch.warnings = records.warnings();
+
+    batch
+}
+
+/// Passes the decoded records on in order, returns true after EndOfFile.
+/// Adds the lines the lenient lexer skipped something on to `warnings`.
+fn feed<W: Write, DWR: DataWriter<W>>(
+    converter: &mut Converter<DWR>,
+    writer: &mut W,
+    batches: &[Batch],
+    warnings: &mut usize,
+) -> Result<bool> {
+    for batch in batches {
+        let mut pos = 0;
+        *warnings += batch.warnings;
+
+        for item in &batch.items {
+            match *item {
+                Item::Data { addr, len, line } => {
+                    converter.data(writer, line, addr, &batch.bytes[pos..pos + len as usize])?;
+                    pos += len as usize;
+                }
+                Item::Record(ref record, line) => {
+                    converter.record(writer, line, **record)?;
+                    if **record == Record::EndOfFile {
+                        return match batch.after_end {
+                            Some(e) => Err(e.into()),
+                            None => Ok(true),
+                        };
+                    }
+                }
+            }
+        }
+
+        match batch.error {
+            Some(e) if e.error == RecordError::MissingEndOfFile => {
+                converter.missing_eof(writer, e)?;
+                return Ok(true);
+            }
+            Some(e) => return Err(e.into()),
+            None => {}
+        }
+    }
+
+    Ok(false)
+}
+
+fn convert_chunked<W, DWR>(
+    input: &[u8],
+    mut writer: W,
+    data_writer: &mut DWR,
+    chunk_sz: usize,
+    checks: Checks,
+) -> Result<ConvertWarnings>
+where
+    W: Write + Send,
+    DWR: DataWriter<W> + Send,
+{
+    let bounds = split(input, chunk_sz);
+    let scans: Vec<(usize, Option<AddressResolver>)> = bounds
+        .par_iter()
+        .map(|&(start, end)| prescan(input, start, end, checks.lex_mode))
+        .collect();
+
+    let mut chunks = Vec::with_capacity(bounds.len());
+    let (mut line, mut resolver) = (1, AddressResolver::new());
+    for (&(start, end), &(newlines, last_resolver)) in bounds.iter().zip(&scans) {
+        chunks.push(Chunk { start, end, newlines, line, resolver });
+        line += newlines;
+        resolver = last_resolver.unwrap_or(resolver);
+    }
+
+    /* Only a few chunks are decoded ahead of the writer, while it writes out
+     * the previous ones, to bound the memory used for huge inputs */
+    let window = 2 * rayon::current_num_threads();
+    let decode_window = |from: usize| -> Vec<Batch> {
+        chunks[cmp::min(from, chunks.len())..cmp::min(from + window, chunks.len())]
+            .par_iter()
+            .map(|chunk| decode(input, chunk, checks))
+            .collect()
+    };
+
+    let mut converter = Converter::new(data_writer, checks);
+    let mut batches = decode_window(0);
+    let mut next = window;
+    let mut warnings = 0;
+
+    while !batches.is_empty() {
+        let (done, next_batches) = rayon::join(
+            || feed(&mut converter, &mut writer, &batches, &mut warnings),
+            || decode_window(next),
+        );
+        if done? {
+            break;
+        }
+
+        batches = next_batches;
+        next += window;
+    }
+
+    writer.flush()?;
+    Ok(converter.into_warnings(warnings))
+}
+
+/// Same as `convert_slice`, but decodes the input on all threads of the
+/// rayon thread pool. The input is split at line boundaries, and a
+/// pre-scan for extended address records gives every piece its address
+/// base, so the output is identical to that of `convert_slice`.
+pub fn convert_parallel<W, DWR>(input: &[u8], writer: W, data_writer: &mut DWR) -> Result<()>
+where
+    W: Write + Send,
+    DWR: DataWriter<W> + Send,
+{
+    convert_chunked(input, writer, data_writer, CHUNK_SZ, Checks::default()).map(|_| ())
+}
+
+/// Converts the input into the data writer, see `convert_chunked`
+struct ConvertChunked<'a, W> {
+    input: &'a [u8],
+    writer: W,
+    chunk_sz: usize,
+    checks: Checks,
+}
+
+impl<W: Write + Send> WithDataWriter<W> for ConvertChunked<'_, W> {
+    type Output = Result<ConvertWarnings>;
+
+    fn run<DWR: DataWriter<W> + Send + 'static>(self, mut data_writer: DWR) -> Self::Output {
+        convert_chunked(self.input, self.writer, &mut data_writer, self.chunk_sz, self.checks)
+    }
+}
+
+pub fn hex2dump_parallel<W: Write + Send>(input: &[u8], writer: W, opts: &DumpOptions) -> Result<ConvertWarnings> {
+    opts.with_data_writer(ConvertChunked { input, writer: BufWriter::new(writer), chunk_sz: CHUNK_SZ, checks: opts.checks() })
+}
+
+pub fn hex2bin_parallel<W: Write + Send>(input: &[u8], writer: W, opts: &BinOptions) -> Result<ConvertWarnings> {
+    opts.with_data_writer(ConvertChunked { input, writer: BufWriter::new(writer), chunk_sz: CHUNK_SZ, checks: opts.checks() })
+}
+
+pub fn hex2hex_parallel<W: Write + Send>(input: &[u8], writer: W, opts: &HexOptions) -> Result<ConvertWarnings> {
+    opts.with_data_writer(ConvertChunked { input, writer: BufWriter::new(writer), chunk_sz: CHUNK_SZ, checks: opts.checks() })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parallel::*;
+    use crate::datawriter::BinDataWriter;
+    use crate::pipeline::{hex2bin_slice, hex2dump_slice, AddrRange, MissingEof};
+
+    const FILES: [&str; 5] = [
+        "hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex",
+        "test/addrspace-gap-mid.in",
+        "test/addrspace-gap-start.in",
+        "test/partial_line.in",
+        "test/squeeze.in",
+    ];
+
+    fn assert_same_output(hex: &[u8], chunk_sz: usize, dump_opts: &DumpOptions) {
+        let mut sequential = Vec::new();
+        let sequential_res = hex2dump_slice(hex, &mut sequential, dump_opts);
+        let mut parallel = Vec::new();
+        let parallel_res = dump_opts.with_data_writer(ConvertChunked { input: hex, writer: &mut parallel, chunk_sz, checks: dump_opts.checks() });
+
+        assert_eq!(parallel, sequential, "chunk size {}", chunk_sz);
+        assert_eq!(
+            parallel_res.map_err(|e| e.to_string()),
+            sequential_res.map_err(|e| e.to_string())
+        );
+
+        let mut sequential = Vec::new();
+        hex2bin_slice(hex, &mut sequential, &BinOptions::default()).ok();
+        let mut parallel = Vec::new();
+        convert_chunked(hex, &mut parallel, &mut BinDataWriter::new(0xff), chunk_sz, Checks::default()).ok();
+
+        assert_eq!(parallel, sequential, "chunk size {}", chunk_sz);
+    }
+
+    #[test]
+    fn it_equals_the_sequential_output() {
+        let annotated = DumpOptions {
+            annotate: true,
+            ranges: vec![AddrRange { start: 0x1000, end: 0x20000 }],
+            ..DumpOptions::default()
+        };
+
+        for file in FILES {
+            let hex = std::fs::read(file).unwrap();
+
+            for chunk_sz in [64, 4096, CHUNK_SZ] {
+                assert_same_output(&hex, chunk_sz, &DumpOptions::default());
+                assert_same_output(&hex, chunk_sz, &annotated);
+            }
+        }
+    }
+
+    #[test]
+    fn it_applies_extended_addresses_from_earlier_chunks() {
+        let hex = b":020000021200EA\n:0400100001020304E2\n:020000040002F8\n:02000000AABB99\n\n\n:0100020011EC\n:00000001FF\n";
+
+        for chunk_sz in [1, 20, 40] {
+            assert_same_output(hex, chunk_sz, &DumpOptions::default());
+        }
+    }
+
+    #[test]
+    fn it_stops_at_the_same_error() {
+        let hex = b":0100000000FF\n:0100010000FF\n:0100020011EC\n";
+        let mut out = Vec::new();
+
+        let err = convert_chunked(&hex[..], &mut out, &mut BinDataWriter::new(0xff), 1, Checks::default()).unwrap_err();
+
+        assert_eq!(err.to_string(), "Checksum mismatch, expected 0xfe found 0xff on line 2");
+
+        for hex in [&b""[..], b":0100000000FF\n", b":0100000000FF\n:01000", b":00000001FF\ngarbage"] {
+            for chunk_sz in [1, 7, 4096] {
+                assert_same_output(hex, chunk_sz, &DumpOptions::default());
+            }
+        }
+    }
+
+    #[test]
+    fn it_checks_the_input_like_the_sequential_conversion() {
+        let opts = BinOptions {
+            check_overlaps: true,
+            missing_eof: MissingEof::Ignore,
+            reject_data_after_eof: true,
+            ..BinOptions::default()
+        };
+
+        for hex in [
+            &b":03000000010203F7\n:0100030004F8\n"[..],
+            b":03000000010203F7\n:0100010002FC\n:00000001FF\n",
+            b":03000000010203F7\n:01000100AA54\n:00000001FF\n",
+            b":03000000010203F7\n:00000001FF\n\n:00000001FF\n",
+        ] {
+            let mut sequential = Vec::new();
+            let sequential_res = hex2bin_slice(hex, &mut sequential, &opts);
+
+            for chunk_sz in [1, 20, 4096] {
+                let mut parallel = Vec::new();
+                let parallel_res = opts.with_data_writer(ConvertChunked { input: hex, writer: &mut parallel, chunk_sz, checks: opts.checks() });
+
+                assert_eq!(parallel, sequential);
+                assert_eq!(
+                    parallel_res.map_err(|e| e.to_string()),
+                    sequential_res.as_ref().cloned().map_err(|e| e.to_string())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_skips_comments_like_the_sequential_conversion() {
+        let mut hex = b"; :020000040005F5\n:020000040001F9\n".to_vec();
+        hex.extend_from_slice(include_bytes!("../test/lenient.in"));
+        let opts = BinOptions { lex_mode: LexMode::Lenient(Comments::all()), ..BinOptions::default() };
+        let checks = opts.checks();
+
+        let mut sequential = Vec::new();
+        let sequential_warnings = hex2bin_slice(&hex, &mut sequential, &opts).unwrap();
+        let mut records = SliceRecords::with_mode(&hex, checks.lex_mode);
+        records.by_ref().for_each(drop);
+
+        for chunk_sz in [1, 20, 4096] {
+            let mut parallel = Vec::new();
+            let parallel_warnings = convert_chunked(&hex, &mut parallel, &mut BinDataWriter::new(0xff), chunk_sz, checks).unwrap();
+            assert_eq!(parallel, sequential, "chunk size {}", chunk_sz);
+            assert_eq!(parallel_warnings, sequential_warnings, "chunk size {}", chunk_sz);
+
+            /* Every line up to the EndOfFile record is counted by exactly one chunk */
+            let (mut line, mut warnings) = (1, 0);
+            for (start, end) in split(&hex, chunk_sz) {
+                let (newlines, _) = prescan(&hex, start, end, checks.lex_mode);
+                let chunk = Chunk { start, end, newlines, line, resolver: AddressResolver::new() };
+                let batch = decode(&hex, &chunk, checks);
+                warnings += batch.warnings;
+                line += newlines;
+
+                if batch.items.iter().any(|item| matches!(item, Item::Record(record, _) if **record == Record::EndOfFile)) {
+                    break;
+                }
+            }
+            assert_eq!(warnings, records.warnings(), "chunk size {}", chunk_sz);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use core::cmp;
+
+use crate::record::*;
+
+/// Output of `Parser::feed`
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A record and the line it was found on
+    Record(usize, Record),
+    /// A record that could not be decoded, parsing resumes on the next line
+    Error(LineError),
+}
+
+/// Push-based parser for input that arrives in pieces, such as from a UART
+/// or a socket.
+///
+/// A record split across calls to `feed` is held in a fixed size buffer
+/// until the rest of it arrives. Input after the EndOfFile record is only
+/// checked for anything but whitespace, see `data_after_end`.
+pub struct Parser {
+    buf: [u8; MAX_RECORD_SZ],
+    len: usize,
+    line: usize,
+    /// Set after an error, input is ignored up to the next line
+    skip_line: bool,
+    done: bool,
+    /// Line of the first non-whitespace character after the EndOfFile record
+    data_after_end: Option<usize>,
+    mode: LexMode,
+    /// A '/' ended the previous input, it may start a comment
+    pending_slash: bool,
+    warnings: Warnings,
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::with_mode(LexMode::Strict)
+    }
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn with_mode(mode: LexMode) -> Parser {
+        Parser {
+            buf: [0u8; MAX_RECORD_SZ],
+            len: 0,
+            line: 1,
+            skip_line: false,
+            done: false,
+            data_after_end: None,
+            mode,
+            pending_slash: false,
+            warnings: Warnings::default(),
+        }
+    }
+
+    /// Number of lines on which something was skipped in lenient mode
+    pub fn warnings(&self) -> usize {
+        self.warnings.count
+    }
+
+    /// True once the EndOfFile record has been parsed
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// The error for data following the EndOfFile record, if any was fed
+    pub fn data_after_end(&self) -> Option<LineError> {
+        self.data_after_end
+            .map(|line| LineError { line, error: RecordError::DataAfterEndOfFile })
+    }
+
+    /// Parses the next piece of input, yielding an event for every record
+    /// completed by it. Input left in the returned iterator when it is
+    /// dropped is lost.
+    pub fn feed<'a>(&'a mut self, input: &'a [u8]) -> impl Iterator<Item = Event> + 'a {
+        Feed { parser: self, input }
+    }
+
+    /// Ends the input, fails if it stopped in the middle of a record or
+    /// before the EndOfFile record. A '/' ending the input, which never
+    /// became a comment, counts as a truncated record or as data after the
+    /// EndOfFile record.
+    pub fn finish(&mut self) -> Result<(), LineError> {
+        let pending_slash = core::mem::take(&mut self.pending_slash);
+        if pending_slash && self.done && self.data_after_end.is_none() {
+            self.data_after_end = Some(self.line);
+        }
+
+        let error = if self.len > 0 || (pending_slash && !self.done) {
+            RecordError::Truncated
+        } else if !self.done {
+            RecordError::MissingEndOfFile
+        } else {
+            return Ok(());
+        };
+
+        self.len = 0;
+        Err(LineError { line: self.line, error })
+    }
+
+    /// Skips the first byte of `input` if the lexer accepts it, which is
+    /// neither a line ending nor a start code. Returns false if it does not.
+    fn skip_lenient(&mut self, input: &[u8]) -> bool {
+        let comments = match self.mode {
+            LexMode::Lenient(comments) => comments,
+            LexMode::Strict => return false,
+        };
+
+        match comments.skip(input) {
+            Skip::Char => {}
+            /* The rest of the line is skipped like after an error */
+            Skip::Line => self.skip_line = true,
+            Skip::NeedMore => {
+                self.pending_slash = true;
+                return true;
+            }
+            Skip::No => return false,
+        }
+        self.warnings.warn(self.line);
+
+        true
+    }
+
+    /// Ends the comment started by the '/' that ended the previous input,
+    /// returns false if `c` does not continue it
+    fn continue_comment(&mut self, c: u8) -> bool {
+        self.pending_slash = false;
+        if c != b'/' {
+            return false;
+        }
+        self.skip_line = true;
+        self.warnings.warn(self.line);
+
+        true
+    }
+
+    fn error(&mut self, error: RecordError) -> Event {
+        self.len = 0;
+        self.skip_line = true;
+        Event::Error(LineError { line: self.line, error })
+    }
+
+    /// Consumes input until a record is complete or fails, returns the
+    /// event and the number of bytes consumed
+    fn parse(&mut self, input: &[u8]) -> (Option<Event>, usize) {
+        if self.done {
+            self.scan_after_end(input);
+            return (None, input.len());
+        }
+
+        let mut pos = 0;
+
+        while pos < input.len() && !self.done {
+            let c = input[pos];
+
+            if self.pending_slash {
+                if !self.continue_comment(c) {
+                    return (Some(self.error(RecordError::MissingStartCode)), pos);
+                }
+                pos += 1;
+                continue;
+            }
+            let is_line_end = c == b'\r' || c == b'\n';
+            if self.len == 0 && !self.skip_line && !is_line_end && c != START_CODE && self.skip_lenient(&input[pos..]) {
+                pos += 1;
+                continue;
+            }
+
+            if c == b'\r' || c == b'\n' {
+                /* A line ending inside a record is left for the next call */
+                if self.len > 0 {
+                    return (Some(self.error(RecordError::Truncated)), pos);
+                }
+                if c == b'\n' {
+                    self.line += 1;
+                    self.skip_line = false;
+                }
+                pos += 1;
+                continue;
+            }
+
+            if self.skip_line {
+                pos += 1;
+                continue;
+            }
+            if self.len == 0 && c != START_CODE {
+                return (Some(self.error(RecordError::MissingStartCode)), pos + 1);
+            }
+
+            let need = if self.len < HEADER_SZ {
+                HEADER_SZ
+            } else {
+                match record_size(&self.buf[..HEADER_SZ]) {
+                    Ok(sz) => sz,
+                    Err(e) => return (Some(self.error(e)), pos),
+                }
+            };
+
+            /* Take bytes up to the end of the record or the line */
+            let avail = &input[pos..cmp::min(input.len(), pos + need - self.len)];
+            let n = avail
+                .iter()
+                .position(|&c| c == b'\r' || c == b'\n')
+                .unwrap_or(avail.len());
+            self.buf[self.len..self.len + n].copy_from_slice(&avail[..n]);
+            self.len += n;
+            pos += n;
+
+            if self.len < HEADER_SZ || self.len < need {
+                continue;
+            }
+
+            let record = match record_size(&self.buf[..HEADER_SZ]) {
+                Ok(sz) if sz == self.len => decode_record(&self.buf[..sz]),
+                Ok(_) => continue,
+                Err(e) => Err(e),
+            };
+
+            let event = match record {
+                Ok(record) => {
+                    self.len = 0;
+                    self.done = record == Record::EndOfFile;
+                    Event::Record(self.line, record)
+                }
+                Err(e) => self.error(e),
+            };
+            return (Some(event), pos);
+        }
+
+        (None, input.len())
+    }
+
+    fn scan_after_end(&mut self, input: &[u8]) {
+        for (pos, &c) in input.iter().enumerate() {
+            if self.data_after_end.is_some() {
+                return;
+            }
+
+            let accepted = if self.pending_slash {
+                self.continue_comment(c)
+            } else if c == b'\n' {
+                self.line += 1;
+                self.skip_line = false;
+                true
+            } else {
+                self.skip_line || c.is_ascii_whitespace() || self.skip_lenient(&input[pos..])
+            };
+            if !accepted {
+                self.data_after_end = Some(self.line);
+            }
+        }
+    }
+}
+
+struct Feed<'a> {
+    parser: &'a mut Parser,
+    input: &'a [u8],
+}
+
+impl<'a> Iterator for Feed<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let (event, consumed) = self.parser.parse(self.input);
+        self.input = &self.input[consumed..];
+        event
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::parser::*;
+    use crate::records::Records;
+
+    #[test]
+    fn it_parses_input_split_at_any_point() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let records: Vec<(usize, Record)> = Records::new(&hex[..])
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+
+        for chunk_size in [1, 7, 43, 4096] {
+            let mut parser = Parser::new();
+            let mut events = Vec::new();
+
+            for chunk in hex.chunks(chunk_size) {
+                events.extend(parser.feed(chunk));
+            }
+            parser.finish().unwrap();
+
+            let expected: Vec<Event> = records.iter().map(|&(line, r)| Event::Record(line, r)).collect();
+            assert_eq!(events, expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn it_resumes_on_the_next_line_after_an_error() {
+        let hex = b":0100000000FF\r\n:0100010000FF\r\ngarbage\n:0100\n:0100020011EC\n:00000001FF\n:0100";
+        let mut parser = Parser::new();
+
+        let events: Vec<Event> = parser.feed(hex).collect();
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Record(1, Record::Data { addr: 0, bytes: DataBytes::new(&[0]) }),
+                Event::Error(LineError {
+                    line: 2,
+                    error: RecordError::ChecksumMismatch { expected: 0xfe, found: 0xff }
+                }),
+                Event::Error(LineError { line: 3, error: RecordError::MissingStartCode }),
+                Event::Error(LineError { line: 4, error: RecordError::Truncated }),
+                Event::Record(5, Record::Data { addr: 2, bytes: DataBytes::new(&[0x11]) }),
+                Event::Record(6, Record::EndOfFile),
+            ]
+        );
+        assert!(parser.is_done());
+        assert_eq!(parser.finish(), Ok(()));
+    }
+
+    #[test]
+    fn it_fails_to_finish_early() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed(b":0100000000FF\n:01").count(), 1);
+        assert_eq!(
+            parser.finish(),
+            Err(LineError { line: 2, error: RecordError::Truncated })
+        );
+
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed(b":0100000000FF\n").count(), 1);
+        assert_eq!(
+            parser.finish(),
+            Err(LineError { line: 2, error: RecordError::MissingEndOfFile })
+        );
+    }
+
+    #[test]
+    fn it_finds_data_after_the_end() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.feed(b":00000001FF\r\n\r\n").count(), 1);
+        assert_eq!(parser.feed(b"  \t").count(), 0);
+        assert_eq!(parser.data_after_end(), None);
+
+        assert_eq!(parser.feed(b"\n:0100").count(), 0);
+        assert_eq!(
+            parser.data_after_end(),
+            Some(LineError { line: 4, error: RecordError::DataAfterEndOfFile })
+        );
+        assert_eq!(parser.finish(), Ok(()));
+    }
+
+    #[test]
+    fn it_skips_comments_split_at_any_point_in_lenient_mode() {
+        let mut hex = include_bytes!("../test/lenient.in").to_vec();
+        hex.extend_from_slice(b"\r\n// after the end\r\n");
+        let mode = LexMode::Lenient(Comments::all());
+        let mut records = SliceRecords::with_mode(&hex, mode);
+        let expected: Vec<Event> = (&mut records).map(|res| {
+            let (line, record) = res.unwrap();
+            Event::Record(line, record)
+        }).collect();
+
+        for chunk_size in 1..8 {
+            let mut parser = Parser::with_mode(mode);
+            let mut events = Vec::new();
+
+            for chunk in hex.chunks(chunk_size) {
+                events.extend(parser.feed(chunk));
+            }
+            parser.finish().unwrap();
+
+            assert_eq!(events, expected, "chunk size {}", chunk_size);
+            assert_eq!(parser.data_after_end(), None);
+            assert_eq!(parser.warnings(), records.warnings() + 2);
+        }
+
+        let mut parser = Parser::with_mode(mode);
+        assert_eq!(parser.feed(b"/").count(), 0);
+        assert_eq!(
+            parser.feed(b":00000001FF\n").next(),
+            Some(Event::Error(LineError { line: 1, error: RecordError::MissingStartCode }))
+        );
+    }
+
+    #[test]
+    fn it_fails_on_a_slash_ending_the_input_in_lenient_mode() {
+        let mode = LexMode::Lenient(Comments::all());
+
+        let mut parser = Parser::with_mode(mode);
+        assert_eq!(parser.feed(b":0100000000FF\n/").count(), 1);
+        assert_eq!(
+            parser.finish(),
+            Err(LineError { line: 2, error: RecordError::Truncated })
+        );
+
+        let mut parser = Parser::with_mode(mode);
+        assert_eq!(parser.feed(b":00000001FF\n/").count(), 1);
+        assert_eq!(parser.finish(), Ok(()));
+        assert_eq!(
+            parser.data_after_end(),
+            Some(LineError { line: 2, error: RecordError::DataAfterEndOfFile })
+        );
+    }
+}
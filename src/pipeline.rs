@@ -0,0 +1,706 @@
+use std::cmp;
+use std::io::{self, BufWriter, Read, Result, Write};
+use std::str::FromStr;
+
+use crate::chunks::AddressResolver;
+use crate::color::Colors;
+use crate::datawriter::*;
+use crate::overlaps::OverlapChecker;
+use crate::record::{Comments, LexMode, LineError, Record, RecordError, SliceRecords};
+use crate::records::Records;
+use crate::verify::Problem;
+
+/// Bytes per row handed to the data writer
+const ROW_SZ: usize = 16;
+
+/// Feeds records to a data writer: resolves their addresses, re-chunks
+/// contiguous data into rows of `ROW_SZ` bytes and wraps every contiguous
+/// run in begin_segment/end_segment calls
+pub struct Converter<'a, DWR> {
+    data_writer: &'a mut DWR,
+    resolver: AddressResolver,
+    /// Set when overlapping data records are checked for
+    overlaps: Option<OverlapChecker>,
+    missing_eof: MissingEof,
+    warnings: ConvertWarnings,
+    row: [u8; ROW_SZ],
+    row_len: usize,
+    row_addr: u32,
+    /// Address following the data pushed so far, `None` between segments
+    next_addr: Option<u64>,
+}
+
+impl<'a, DWR> Converter<'a, DWR> {
+    pub fn new(data_writer: &'a mut DWR, checks: Checks) -> Converter<'a, DWR> {
+        Converter {
+            data_writer,
+            resolver: AddressResolver::new(),
+            overlaps: if checks.overlaps { Some(OverlapChecker::new()) } else { None },
+            missing_eof: checks.missing_eof,
+            warnings: ConvertWarnings::default(),
+            row: [0u8; ROW_SZ],
+            row_len: 0,
+            row_addr: 0,
+            next_addr: None,
+        }
+    }
+
+    fn push<W: Write>(&mut self, writer: &mut W, addr: u32, mut bytes: &[u8]) -> Result<()>
+    where
+        DWR: DataWriter<W>,
+    {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        /* Rows must never span across a hole in the address space */
+        if self.next_addr != Some(addr as u64) {
+            self.end_segment(writer)?;
+            self.data_writer.begin_segment(writer, addr)?;
+            self.row_addr = addr;
+        }
+        self.next_addr = Some(addr as u64 + bytes.len() as u64);
+
+        while !bytes.is_empty() {
+            /* Full rows are passed on without copying them */
+            if self.row_len == 0 && bytes.len() >= ROW_SZ {
+                let (row, rest) = bytes.split_at(ROW_SZ);
+                self.data_writer.write(writer, self.row_addr, row)?;
+                self.row_addr = self.row_addr.wrapping_add(ROW_SZ as u32);
+                bytes = rest;
+                continue;
+            }
+
+            let n = cmp::min(ROW_SZ - self.row_len, bytes.len());
+            self.row[self.row_len..self.row_len + n].copy_from_slice(&bytes[..n]);
+            self.row_len += n;
+            bytes = &bytes[n..];
+
+            if self.row_len == ROW_SZ {
+                self.data_writer.write(writer, self.row_addr, &self.row)?;
+                self.row_addr = self.row_addr.wrapping_add(ROW_SZ as u32);
+                self.row_len = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes the data of the record on `line`. When checking for overlaps,
+    /// data conflicting with earlier data is an error and repeated data is
+    /// left out and added to the warnings.
+    pub(crate) fn data<W: Write>(&mut self, writer: &mut W, line: usize, addr: u32, bytes: &[u8]) -> Result<()>
+    where
+        DWR: DataWriter<W>,
+    {
+        let overlaps = match self.overlaps.as_mut() {
+            Some(checker) => {
+                let mut overlaps = Vec::new();
+                checker.write(line, addr, bytes, &mut overlaps);
+                overlaps
+            }
+            None => return self.push(writer, addr, bytes),
+        };
+
+        /* Offset into `bytes` of the data not yet pushed */
+        let mut pos = 0;
+        for overlap in overlaps {
+            let problem = Problem::from(overlap);
+            if !overlap.identical {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, problem.to_string()));
+            }
+            self.warnings.overlaps.push(problem);
+
+            let (start, end) = ((overlap.start - addr) as usize, (overlap.end - addr as u64) as usize);
+            self.push(writer, addr.wrapping_add(pos as u32), &bytes[pos..start])?;
+            pos = end;
+        }
+
+        self.push(writer, addr.wrapping_add(pos as u32), &bytes[pos..])
+    }
+
+    /// Passes on the record on `line`, EndOfFile finishes the data writer
+    pub fn record<W: Write>(&mut self, writer: &mut W, line: usize, record: Record) -> Result<()>
+    where
+        DWR: DataWriter<W>,
+    {
+        match record {
+            Record::Data { addr, bytes } => {
+                let resolver = self.resolver;
+                for (addr, piece) in resolver.resolve(addr, &bytes) {
+                    self.data(writer, line, addr, piece)?;
+                }
+                Ok(())
+            }
+            Record::StartSegmentAddr { cs, ip } => {
+                self.data_writer.start_addr(writer, StartAddr::Segment { cs, ip })
+            }
+            Record::StartLinearAddr(eip) => self.data_writer.start_addr(writer, StartAddr::Linear(eip)),
+            Record::EndOfFile => {
+                self.end_segment(writer)?;
+                self.data_writer.finish(writer)
+            }
+            // These records affect the following data addresses
+            Record::ExtendedSegmentAddr(_) | Record::ExtendedLinearAddr(_) => {
+                self.end_segment(writer)?;
+                self.resolver.apply(&record);
+                Ok(())
+            }
+        }
+    }
+
+    /// Handles input that ended without an EndOfFile record, unless that is
+    /// an error the data converted so far is finished as if it had one and
+    /// with `MissingEof::Warn` the error is added to the warnings
+    pub(crate) fn missing_eof<W: Write>(&mut self, writer: &mut W, e: LineError) -> Result<()>
+    where
+        DWR: DataWriter<W>,
+    {
+        match self.missing_eof {
+            MissingEof::Error => return Err(e.into()),
+            MissingEof::Warn => self.warnings.missing_eof = Some(e),
+            MissingEof::Ignore => {}
+        }
+
+        self.record(writer, e.line, Record::EndOfFile)
+    }
+
+    /// What the conversion warned about, `skipped_lines` being the number
+    /// of lines the lenient lexer skipped something on
+    pub(crate) fn into_warnings(self, skipped_lines: usize) -> ConvertWarnings {
+        ConvertWarnings {
+            skipped_lines,
+            ..self.warnings
+        }
+    }
+
+    /// Writes the partial row left over, if any, and ends the current segment
+    fn end_segment<W: Write>(&mut self, writer: &mut W) -> Result<()>
+    where
+        DWR: DataWriter<W>,
+    {
+        if self.next_addr.take().is_none() {
+            return Ok(());
+        }
+
+        if self.row_len > 0 {
+            self.data_writer.write(writer, self.row_addr, &self.row[..self.row_len])?;
+            self.row_len = 0;
+        }
+
+        self.data_writer.end_segment(writer)
+    }
+}
+
+/// What a conversion that succeeded warned about, for the caller to report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConvertWarnings {
+    /// Data repeating earlier data, which was left out, see `check_overlaps`
+    pub overlaps: Vec<Problem>,
+    /// Where the input ended without an EndOfFile record, with
+    /// `MissingEof::Warn`
+    pub missing_eof: Option<LineError>,
+    /// Number of lines on which comments, whitespace or end of file markers
+    /// were skipped, see `LexMode::Lenient`
+    pub skipped_lines: usize,
+}
+
+/// How conversions treat input ending without an EndOfFile record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingEof {
+    #[default]
+    Error,
+    /// Finish the output and return the error as a warning, see
+    /// `ConvertWarnings::missing_eof`
+    Warn,
+    /// Finish the output as if the record was there
+    Ignore,
+}
+
+impl FromStr for MissingEof {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<MissingEof, String> {
+        match s {
+            "error" => Ok(MissingEof::Error),
+            "warn" => Ok(MissingEof::Warn),
+            "ignore" => Ok(MissingEof::Ignore),
+            _ => Err(format!("Expected error, warn or ignore, found {:?}", s)),
+        }
+    }
+}
+
+impl FromStr for Comments {
+    type Err = String;
+
+    /// Parses a comma separated list of comment prefixes: `;`, `#` and `//`
+    fn from_str(s: &str) -> std::result::Result<Comments, String> {
+        let mut comments = Comments::none();
+        for prefix in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match prefix {
+                ";" => comments.semicolon = true,
+                "#" => comments.hash = true,
+                "//" => comments.double_slash = true,
+                _ => return Err(format!("Expected ;, # or //, found {:?}", prefix)),
+            }
+        }
+
+        Ok(comments)
+    }
+}
+
+/// Checks of the input made while converting, taken from the options
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Checks {
+    pub overlaps: bool,
+    pub missing_eof: MissingEof,
+    pub data_after_eof: bool,
+    pub lex_mode: LexMode,
+}
+
+/// The error of a conversion that failed for want of an EndOfFile record
+fn missing_eof_error(e: &io::Error) -> Option<LineError> {
+    e.get_ref()?
+        .downcast_ref::<LineError>()
+        .filter(|e| e.error == RecordError::MissingEndOfFile)
+        .copied()
+}
+
+/// Iterators over records that can check the input after the last one
+pub(crate) trait RecordSource {
+    fn next_record(&mut self) -> Option<Result<(usize, Record)>>;
+    fn check_end(&mut self) -> Result<()>;
+    fn warnings(&self) -> usize;
+}
+
+impl<R: Read> RecordSource for Records<R> {
+    fn next_record(&mut self) -> Option<Result<(usize, Record)>> {
+        self.next()
+    }
+
+    fn check_end(&mut self) -> Result<()> {
+        Records::check_end(self)
+    }
+
+    fn warnings(&self) -> usize {
+        Records::warnings(self)
+    }
+}
+
+impl RecordSource for SliceRecords<'_> {
+    fn next_record(&mut self) -> Option<Result<(usize, Record)>> {
+        self.next().map(|res| res.map_err(Into::into))
+    }
+
+    fn check_end(&mut self) -> Result<()> {
+        Ok(SliceRecords::check_end(self)?)
+    }
+
+    fn warnings(&self) -> usize {
+        SliceRecords::warnings(self)
+    }
+}
+
+/// Feeds the records to `data_writer`, stopping at the first error
+pub(crate) fn convert_records<S, W, DWR>(
+    mut records: S,
+    mut writer: W,
+    data_writer: &mut DWR,
+    checks: Checks,
+) -> Result<ConvertWarnings>
+where
+    S: RecordSource,
+    W: Write,
+    DWR: DataWriter<W>,
+{
+    let mut converter = Converter::new(data_writer, checks);
+
+    while let Some(res) = records.next_record() {
+        let (line, record) = match res {
+            Ok(res) => res,
+            Err(e) => match missing_eof_error(&e) {
+                Some(e) => {
+                    converter.missing_eof(&mut writer, e)?;
+                    break;
+                }
+                None => return Err(e),
+            },
+        };
+        converter.record(&mut writer, line, record)?;
+
+        if record == Record::EndOfFile && checks.data_after_eof {
+            records.check_end()?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(converter.into_warnings(records.warnings()))
+}
+
+/// Reads an Intel HEX file and feeds its data to `data_writer`, which is
+/// handed `writer` on every call. See `DataWriter` for the order of calls.
+///
+/// `writer` is flushed at the end but not buffered, wrap it in a `BufWriter`
+/// if the data writer makes many small writes.
+pub fn convert<R: Read, W: Write, DWR: DataWriter<W>>(
+    reader: R,
+    writer: W,
+    data_writer: &mut DWR,
+) -> Result<()> {
+    convert_records(Records::new(reader), writer, data_writer, Checks::default()).map(|_| ())
+}
+
+/// Same as `convert`, for an Intel HEX file held in memory, such as a
+/// memory-mapped file. Records are decoded straight from `input` without
+/// copying it into a read buffer first.
+pub fn convert_slice<W: Write, DWR: DataWriter<W>>(
+    input: &[u8],
+    writer: W,
+    data_writer: &mut DWR,
+) -> Result<()> {
+    convert_records(SliceRecords::new(input), writer, data_writer, Checks::default()).map(|_| ())
+}
+
+/// Half-open address range `[start, end)` used to restrict the output. The
+/// end is a `u64` so a range can include address 0xFFFFFFFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddrRange {
+    pub start: u32,
+    pub end: u64,
+}
+
+fn parse_addr(s: &str) -> std::result::Result<u32, String> {
+    let s = s.trim();
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|e| format!("Invalid address {:?}: {}", s, e))
+}
+
+/// Parses an exclusive end or a length, which may be 0x100000000
+fn parse_end(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|e| format!("Invalid address {:?}: {}", s, e))
+}
+
+impl FromStr for AddrRange {
+    type Err = String;
+
+    /// Parses `START..END` or `START+LEN`, numbers are decimal or 0x prefixed hex
+    fn from_str(s: &str) -> std::result::Result<AddrRange, String> {
+        let (start, end) = if let Some((start, end)) = s.split_once("..") {
+            (parse_addr(start)?, parse_end(end)?)
+        } else if let Some((start, len)) = s.split_once('+') {
+            let start = parse_addr(start)?;
+            (start, start as u64 + parse_end(len)?)
+        } else {
+            return Err(format!("Expected START..END or START+LEN, found {:?}", s));
+        };
+
+        if end > 1 << 32 {
+            return Err(format!("Range {:?} exceeds the 32-bit address space", s));
+        }
+        if end < start as u64 {
+            return Err(format!("Range {:?} ends before it starts", s));
+        }
+
+        Ok(AddrRange { start, end })
+    }
+}
+
+/// Sorts and merges ranges into the form expected by `RangeDataWriter`
+fn merge_ranges(ranges: &[AddrRange]) -> Vec<(u64, u64)> {
+    let mut sorted: Vec<(u64, u64)> = ranges
+        .iter()
+        .filter(|r| (r.start as u64) < r.end)
+        .map(|r| (r.start as u64, r.end))
+        .collect();
+    sorted.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(sorted.len());
+    for (start, end) in sorted {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = cmp::max(last.1, end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Something done with the data writer the options describe, which is
+/// handed over with its concrete type so that the calls to it are not
+/// virtual. See `DumpOptions::with_data_writer`.
+pub(crate) trait WithDataWriter<W: Write> {
+    type Output;
+
+    fn run<DWR: DataWriter<W> + Send + 'static>(self, data_writer: DWR) -> Self::Output;
+}
+
+/// Converts the records into the data writer, see `convert_records`
+struct ConvertRecords<S, W> {
+    records: S,
+    writer: W,
+    checks: Checks,
+}
+
+impl<S: RecordSource, W: Write> WithDataWriter<W> for ConvertRecords<S, W> {
+    type Output = Result<ConvertWarnings>;
+
+    fn run<DWR: DataWriter<W> + Send + 'static>(self, mut data_writer: DWR) -> Self::Output {
+        convert_records(self.records, self.writer, &mut data_writer, self.checks)
+    }
+}
+
+/// Runs `with` on the data writer, only passing data within `ranges` to it.
+/// Without ranges the data writer is used as is.
+fn with_ranges<W, DWR, F>(data_writer: DWR, ranges: &[AddrRange], with: F) -> F::Output
+where
+    W: Write,
+    DWR: DataWriter<W> + Send + 'static,
+    F: WithDataWriter<W>,
+{
+    if ranges.is_empty() {
+        with.run(data_writer)
+    } else {
+        with.run(RangeDataWriter::new(data_writer, merge_ranges(ranges)))
+    }
+}
+
+/// Tools whose dump output can be reproduced exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compat {
+    /// hex2dump.py of python-intelhex, including its start address header
+    /// and `--` filled address gaps
+    Python,
+}
+
+impl FromStr for Compat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Compat, String> {
+        match s {
+            "python" => Ok(Compat::Python),
+            _ => Err(format!("Unknown compat mode: {}", s)),
+        }
+    }
+}
+
+/// Options controlling the human-readable dump
+#[derive(Debug, Default, Clone)]
+pub struct DumpOptions {
+    /// Mimic the output of another tool instead of the intelhexes layout
+    pub compat: Option<Compat>,
+    /// Collapse consecutive identical rows into a single `*` line, like
+    /// `hexdump -C`. Runs are never squeezed across segment boundaries.
+    pub squeeze: bool,
+    /// Print `-- segment` and `-- gap` lines between contiguous regions of
+    /// data, and a footer with the total byte and segment count
+    pub annotate: bool,
+    /// Only dump data within these address ranges, everything if empty
+    pub ranges: Vec<AddrRange>,
+    /// Colour bytes by class using ANSI escape codes
+    pub color: bool,
+    /// Byte pattern to highlight when colouring
+    pub highlight_bytes: Vec<u8>,
+    /// Address ranges to highlight when colouring
+    pub highlight_ranges: Vec<AddrRange>,
+    /// Fail on data records writing different bytes to the same address,
+    /// skip those repeating earlier data and return them as warnings
+    pub check_overlaps: bool,
+    /// What to do when the input ends without an EndOfFile record
+    pub missing_eof: MissingEof,
+    /// Fail if anything but whitespace follows the EndOfFile record, rather
+    /// than ignoring it
+    pub reject_data_after_eof: bool,
+    /// What may appear between records
+    pub lex_mode: LexMode,
+}
+
+impl DumpOptions {
+    fn hex_data_writer(&self) -> HexDataWriter {
+        let colors = if self.color {
+            Some(Colors::new(
+                self.highlight_bytes.clone(),
+                merge_ranges(&self.highlight_ranges),
+            ))
+        } else {
+            None
+        };
+
+        HexDataWriter::new(self.squeeze, colors)
+    }
+
+    /// Runs `with` on the data writer producing the dump described by
+    /// these options
+    pub(crate) fn with_data_writer<W: Write, F: WithDataWriter<W>>(&self, with: F) -> F::Output {
+        match self.compat {
+            None if self.annotate => {
+                with_ranges(AnnotatedDataWriter::new(self.hex_data_writer()), &self.ranges, with)
+            }
+            None => with_ranges(self.hex_data_writer(), &self.ranges, with),
+            Some(Compat::Python) => with_ranges(PyHexDataWriter::new(), &self.ranges, with),
+        }
+    }
+
+    pub(crate) fn checks(&self) -> Checks {
+        Checks {
+            overlaps: self.check_overlaps,
+            missing_eof: self.missing_eof,
+            data_after_eof: self.reject_data_after_eof,
+            lex_mode: self.lex_mode,
+        }
+    }
+}
+
+pub fn hex2dump<R: Read, W: Write>(reader: R, writer: W) -> Result<()> {
+    hex2dump_with(reader, writer, &DumpOptions::default()).map(|_| ())
+}
+
+pub fn hex2dump_with<R: Read, W: Write>(reader: R, writer: W, opts: &DumpOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: Records::with_mode(reader, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
+
+pub fn hex2dump_slice<W: Write>(input: &[u8], writer: W, opts: &DumpOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: SliceRecords::with_mode(input, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
+
+/// Options controlling the binary output
+#[derive(Debug, Clone)]
+pub struct BinOptions {
+    /// Byte used to fill gaps between data
+    pub fill_byte: u8,
+    /// Only output data within these address ranges, everything if empty
+    pub ranges: Vec<AddrRange>,
+    /// Fail on data records writing different bytes to the same address,
+    /// skip those repeating earlier data and return them as warnings
+    pub check_overlaps: bool,
+    /// What to do when the input ends without an EndOfFile record
+    pub missing_eof: MissingEof,
+    /// Fail if anything but whitespace follows the EndOfFile record, rather
+    /// than ignoring it
+    pub reject_data_after_eof: bool,
+    /// What may appear between records
+    pub lex_mode: LexMode,
+}
+
+impl BinOptions {
+    /// Runs `with` on the data writer producing the binary described by
+    /// these options
+    pub(crate) fn with_data_writer<W: Write, F: WithDataWriter<W>>(&self, with: F) -> F::Output {
+        with_ranges(BinDataWriter::new(self.fill_byte), &self.ranges, with)
+    }
+
+    pub(crate) fn checks(&self) -> Checks {
+        Checks {
+            overlaps: self.check_overlaps,
+            missing_eof: self.missing_eof,
+            data_after_eof: self.reject_data_after_eof,
+            lex_mode: self.lex_mode,
+        }
+    }
+}
+
+impl Default for BinOptions {
+    fn default() -> BinOptions {
+        BinOptions {
+            fill_byte: 0xff,
+            ranges: Vec::new(),
+            check_overlaps: false,
+            missing_eof: MissingEof::default(),
+            reject_data_after_eof: false,
+            lex_mode: LexMode::Strict,
+        }
+    }
+}
+
+pub fn hex2bin<R: Read, W: Write>(reader: R, writer: W, fill_byte: u8) -> Result<()> {
+    hex2bin_with(reader, writer, &BinOptions { fill_byte, ..BinOptions::default() }).map(|_| ())
+}
+
+pub fn hex2bin_with<R: Read, W: Write>(reader: R, writer: W, opts: &BinOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: Records::with_mode(reader, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
+
+pub fn hex2bin_slice<W: Write>(input: &[u8], writer: W, opts: &BinOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: SliceRecords::with_mode(input, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
+
+/// Options controlling the Intel HEX output
+#[derive(Debug, Default, Clone)]
+pub struct HexOptions {
+    /// Only output data within these address ranges, everything if empty
+    pub ranges: Vec<AddrRange>,
+    /// Fail on data records writing different bytes to the same address,
+    /// skip those repeating earlier data and return them as warnings
+    pub check_overlaps: bool,
+    /// What to do when the input ends without an EndOfFile record
+    pub missing_eof: MissingEof,
+    /// Fail if anything but whitespace follows the EndOfFile record, rather
+    /// than ignoring it
+    pub reject_data_after_eof: bool,
+    /// What may appear between records
+    pub lex_mode: LexMode,
+}
+
+impl HexOptions {
+    /// Runs `with` on the data writer producing the Intel HEX file
+    /// described by these options
+    pub(crate) fn with_data_writer<W: Write, F: WithDataWriter<W>>(&self, with: F) -> F::Output {
+        with_ranges(IntelHexDataWriter::new(), &self.ranges, with)
+    }
+
+    pub(crate) fn checks(&self) -> Checks {
+        Checks {
+            overlaps: self.check_overlaps,
+            missing_eof: self.missing_eof,
+            data_after_eof: self.reject_data_after_eof,
+            lex_mode: self.lex_mode,
+        }
+    }
+}
+
+/// Rewrites an Intel HEX file in rows of 16 bytes, with ExtendedLinearAddr
+/// records where needed, see `IntelHexDataWriter`
+pub fn hex2hex_with<R: Read, W: Write>(reader: R, writer: W, opts: &HexOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: Records::with_mode(reader, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
+
+pub fn hex2hex_slice<W: Write>(input: &[u8], writer: W, opts: &HexOptions) -> Result<ConvertWarnings> {
+    let checks = opts.checks();
+    opts.with_data_writer(ConvertRecords {
+        records: SliceRecords::with_mode(input, checks.lex_mode),
+        writer: BufWriter::new(writer),
+        checks,
+    })
+}
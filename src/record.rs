@@ -0,0 +1,508 @@
+use core::fmt;
+use core::ops::Deref;
+
+use crate::helpers::*;
+
+/// Longest data payload a record can carry
+pub const MAX_DATA_LEN: usize = 255;
+
+pub const START_CODE: u8 = b':';
+/// ':' + length + address + type
+pub const HEADER_SZ: usize = 9;
+const CHECKSUM_SZ: usize = 2;
+/// Size in ASCII of the longest possible record
+pub const MAX_RECORD_SZ: usize = HEADER_SZ + 2 * MAX_DATA_LEN + CHECKSUM_SZ;
+
+/// Decoded payload of a data record, stored inline to avoid an allocation
+/// per record
+#[derive(Clone, Copy)]
+pub struct DataBytes {
+    buf: [u8; MAX_DATA_LEN],
+    len: u8,
+}
+
+impl DataBytes {
+    pub fn new(bytes: &[u8]) -> DataBytes {
+        let mut d = DataBytes {
+            buf: [0u8; MAX_DATA_LEN],
+            len: bytes.len() as u8,
+        };
+        d.buf[..bytes.len()].copy_from_slice(bytes);
+        d
+    }
+}
+
+impl Deref for DataBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl PartialEq for DataBytes {
+    fn eq(&self, other: &DataBytes) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for DataBytes {}
+
+impl fmt::Debug for DataBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A decoded Intel HEX record
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record {
+    Data { addr: u16, bytes: DataBytes },
+    EndOfFile,
+    /// Segment base, data addresses that follow are offset by `segment << 4`
+    ExtendedSegmentAddr(u16),
+    StartSegmentAddr { cs: u16, ip: u16 },
+    /// Upper 16 bits of the data addresses that follow
+    ExtendedLinearAddr(u16),
+    StartLinearAddr(u32),
+}
+
+/// Reasons a single record can not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordError {
+    MissingStartCode,
+    InvalidHexDigit,
+    /// The length field does not fit the record type
+    InvalidLength { record_type: u8, len: u8 },
+    ChecksumMismatch { expected: u8, found: u8 },
+    UnknownType(u8),
+    /// The line or the input ends in the middle of a record
+    Truncated,
+    /// The input ends without an EndOfFile record
+    MissingEndOfFile,
+    /// Something other than whitespace follows the EndOfFile record
+    DataAfterEndOfFile,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::MissingStartCode => write!(f, "Expected ':' at the start of the record"),
+            RecordError::InvalidHexDigit => write!(f, "Invalid hex digit"),
+            RecordError::InvalidLength { record_type, len } => {
+                write!(f, "Invalid length {:#04x} for record type {:#04x}", len, record_type)
+            }
+            RecordError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Checksum mismatch, expected {:#04x} found {:#04x}",
+                expected, found
+            ),
+            RecordError::UnknownType(t) => write!(f, "Unknown record type {:#04x}", t),
+            RecordError::Truncated => write!(f, "Record ends before its checksum"),
+            RecordError::MissingEndOfFile => write!(f, "Expected an EndOfFile record"),
+            RecordError::DataAfterEndOfFile => write!(f, "Unexpected data after the EndOfFile record"),
+        }
+    }
+}
+
+/// A `RecordError` together with the line it was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineError {
+    pub line: usize,
+    pub error: RecordError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on line {}", self.error, self.line)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LineError {}
+
+#[cfg(feature = "std")]
+impl From<LineError> for std::io::Error {
+    fn from(e: LineError) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Size in ASCII of the record starting with `header`, the first 9 bytes of
+/// a record
+pub fn record_size(header: &[u8]) -> Result<usize, RecordError> {
+    if header[0] != START_CODE {
+        return Err(RecordError::MissingStartCode);
+    }
+    let len = decode_u8(&header[1..3])?;
+
+    Ok(HEADER_SZ + 2 * len as usize + CHECKSUM_SZ)
+}
+
+fn decode_u8(hex: &[u8]) -> Result<u8, RecordError> {
+    hex_to_u8(hex).ok_or(RecordError::InvalidHexDigit)
+}
+
+/// Decodes one complete record, from ':' up to and including the checksum
+pub fn decode_record(buf: &[u8]) -> Result<Record, RecordError> {
+    let sz = record_size(buf)?;
+
+    let mut raw = [0u8; 4 + MAX_DATA_LEN + 1];
+    let raw = &mut raw[..(sz - 1) / 2];
+    if !decode_hex(&buf[1..sz], raw) {
+        return Err(RecordError::InvalidHexDigit);
+    }
+
+    let found = raw.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    if found != 0 {
+        let expected = raw[..raw.len() - 1]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            .wrapping_neg();
+        return Err(RecordError::ChecksumMismatch {
+            expected,
+            found: raw[raw.len() - 1],
+        });
+    }
+
+    let len = raw[0];
+    let addr = u16::from_be_bytes([raw[1], raw[2]]);
+    let record_type = raw[3];
+    let data = &raw[4..raw.len() - 1];
+
+    let expected_len = match record_type {
+        0x00 => len,
+        0x01 => 0,
+        0x02 | 0x04 => 2,
+        0x03 | 0x05 => 4,
+        t => return Err(RecordError::UnknownType(t)),
+    };
+    if len != expected_len {
+        return Err(RecordError::InvalidLength { record_type, len });
+    }
+
+    Ok(match record_type {
+        0x00 => Record::Data {
+            addr,
+            bytes: DataBytes::new(data),
+        },
+        0x01 => Record::EndOfFile,
+        0x02 => Record::ExtendedSegmentAddr(u16::from_be_bytes([data[0], data[1]])),
+        0x03 => Record::StartSegmentAddr {
+            cs: u16::from_be_bytes([data[0], data[1]]),
+            ip: u16::from_be_bytes([data[2], data[3]]),
+        },
+        0x04 => Record::ExtendedLinearAddr(u16::from_be_bytes([data[0], data[1]])),
+        _ => Record::StartLinearAddr(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+    })
+}
+
+/// End of file marker some Windows tools append, Ctrl-Z
+const EOF_MARKER: u8 = 0x1a;
+
+/// Kinds of comments skipped in lenient mode, each runs to the end of its
+/// line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comments {
+    /// `; comment`
+    pub semicolon: bool,
+    /// `# comment`
+    pub hash: bool,
+    /// `// comment`
+    pub double_slash: bool,
+}
+
+impl Comments {
+    pub fn all() -> Comments {
+        Comments { semicolon: true, hash: true, double_slash: true }
+    }
+
+    pub fn none() -> Comments {
+        Comments { semicolon: false, hash: false, double_slash: false }
+    }
+
+    /// What the lenient lexer does with `input`, which is neither a line
+    /// ending nor a start code
+    pub(crate) fn skip(&self, input: &[u8]) -> Skip {
+        match input[0] {
+            c if c.is_ascii_whitespace() || c == EOF_MARKER => Skip::Char,
+            b';' if self.semicolon => Skip::Line,
+            b'#' if self.hash => Skip::Line,
+            b'/' if self.double_slash => match input.get(1) {
+                Some(b'/') => Skip::Line,
+                Some(_) => Skip::No,
+                None => Skip::NeedMore,
+            },
+            _ => Skip::No,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Skip {
+    /// Skip the character
+    Char,
+    /// Skip up to the end of the line
+    Line,
+    /// The input is not skipped
+    No,
+    /// A comment prefix may continue in input not yet available
+    NeedMore,
+}
+
+/// What the lexer accepts between records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LexMode {
+    /// Only line endings
+    #[default]
+    Strict,
+    /// Also whitespace, `\x1A` end of file markers and the given comments.
+    /// Every line they are found on counts as a warning.
+    Lenient(Comments),
+}
+
+/// Counts the lines on which the lenient lexer skipped something
+#[derive(Debug, Default)]
+pub(crate) struct Warnings {
+    pub count: usize,
+    last_line: usize,
+    /// Lines after this one are not counted
+    pub until_line: Option<usize>,
+}
+
+impl Warnings {
+    pub fn warn(&mut self, line: usize) {
+        if line != self.last_line && self.until_line.is_none_or(|until| line <= until) {
+            self.count += 1;
+            self.last_line = line;
+        }
+    }
+}
+
+/// Yields each record of an Intel HEX file held in memory together with its
+/// line number, starting at 1. Records are decoded straight from the input.
+///
+/// Iteration ends after the EndOfFile record.
+pub struct SliceRecords<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+    done: bool,
+    mode: LexMode,
+    pub(crate) warnings: Warnings,
+}
+
+impl<'a> SliceRecords<'a> {
+    pub fn new(input: &'a [u8]) -> SliceRecords<'a> {
+        SliceRecords::with_mode(input, LexMode::Strict)
+    }
+
+    pub fn with_mode(input: &'a [u8], mode: LexMode) -> SliceRecords<'a> {
+        SliceRecords {
+            input,
+            pos: 0,
+            line: 1,
+            done: false,
+            mode,
+            warnings: Warnings::default(),
+        }
+    }
+
+    /// Number of lines on which something was skipped in lenient mode
+    pub fn warnings(&self) -> usize {
+        self.warnings.count
+    }
+
+    /// Skips anything the lexer accepts at the current position other than
+    /// line endings, returns false if there is nothing to skip
+    fn skip_lenient(&mut self) -> bool {
+        let comments = match self.mode {
+            LexMode::Lenient(comments) => comments,
+            LexMode::Strict => return false,
+        };
+
+        match comments.skip(&self.input[self.pos..]) {
+            Skip::Char => self.pos += 1,
+            Skip::Line => {
+                let rest = &self.input[self.pos..];
+                self.pos += rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+            }
+            Skip::No | Skip::NeedMore => return false,
+        }
+        self.warnings.warn(self.line);
+
+        true
+    }
+
+    fn next_record(&mut self) -> Result<(usize, Record), LineError> {
+        loop {
+            match self.input.get(self.pos) {
+                None => {
+                    return Err(LineError { line: self.line, error: RecordError::MissingEndOfFile })
+                }
+                Some(b'\r') => {}
+                Some(b'\n') => self.line += 1,
+                Some(&START_CODE) => break,
+                Some(_) if self.skip_lenient() => continue,
+                Some(_) => break,
+            }
+            self.pos += 1;
+        }
+
+        let line = self.line;
+        let at_line = |error| LineError { line, error };
+
+        let rest = &self.input[self.pos..];
+        if rest.len() < HEADER_SZ {
+            return Err(at_line(RecordError::Truncated));
+        }
+        let sz = record_size(&rest[..HEADER_SZ]).map_err(at_line)?;
+        if rest.len() < sz {
+            return Err(at_line(RecordError::Truncated));
+        }
+        let record = decode_record(&rest[..sz]).map_err(at_line)?;
+
+        self.pos += sz;
+
+        Ok((self.line, record))
+    }
+
+    /// Fails if anything but whitespace follows the records read so far,
+    /// meant to be called after the EndOfFile record
+    pub fn check_end(&mut self) -> Result<(), LineError> {
+        while let Some(&c) = self.input.get(self.pos) {
+            match c {
+                b'\n' => self.line += 1,
+                c if c.is_ascii_whitespace() => {}
+                _ if self.skip_lenient() => continue,
+                _ => return Err(LineError { line: self.line, error: RecordError::DataAfterEndOfFile }),
+            }
+            self.pos += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for SliceRecords<'a> {
+    type Item = Result<(usize, Record), LineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.next_record();
+        self.done = matches!(res, Err(_) | Ok((_, Record::EndOfFile)));
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::*;
+
+    #[test]
+    fn it_decodes_all_record_types() {
+        assert_eq!(decode_record(b":00000001FF"), Ok(Record::EndOfFile));
+        assert_eq!(
+            decode_record(b":020000021200EA"),
+            Ok(Record::ExtendedSegmentAddr(0x1200))
+        );
+        assert_eq!(
+            decode_record(b":0400000300003605BE"),
+            Ok(Record::StartSegmentAddr { cs: 0x0000, ip: 0x3605 })
+        );
+        assert_eq!(
+            decode_record(b":020000040800F2"),
+            Ok(Record::ExtendedLinearAddr(0x0800))
+        );
+        assert_eq!(
+            decode_record(b":04000005000000CD2A"),
+            Ok(Record::StartLinearAddr(0xCD))
+        );
+        assert_eq!(
+            decode_record(b":0B0010006164647265737320676170A7"),
+            Ok(Record::Data {
+                addr: 0x0010,
+                bytes: DataBytes::new(b"address gap")
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_records() {
+        assert_eq!(
+            decode_record(b":00000001FE"),
+            Err(RecordError::ChecksumMismatch { expected: 0xff, found: 0xfe })
+        );
+        assert_eq!(decode_record(b":0000000GFF"), Err(RecordError::InvalidHexDigit));
+        assert_eq!(decode_record(b";00000001FF"), Err(RecordError::MissingStartCode));
+        assert_eq!(decode_record(b":00000006FA"), Err(RecordError::UnknownType(0x06)));
+        assert_eq!(
+            decode_record(b":0100000100FE"),
+            Err(RecordError::InvalidLength { record_type: 0x01, len: 0x01 })
+        );
+    }
+
+    #[test]
+    fn it_iterates_records_in_a_slice() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let mut records = SliceRecords::new(hex);
+
+        assert!(matches!(records.next(), Some(Ok((1, Record::Data { addr: 0x0000, bytes }))) if bytes.len() == 16));
+        assert_eq!(records.nth(2175), Some(Ok((2177, Record::StartSegmentAddr { cs: 0x0000, ip: 0x3605 }))));
+        assert_eq!(records.next(), Some(Ok((2178, Record::EndOfFile))));
+        assert_eq!(records.next(), None);
+    }
+
+    #[test]
+    fn it_reports_slices_ending_early() {
+        let mut records = SliceRecords::new(b":0100000000FF\r\n:0100");
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(records.next(), Some(Err(LineError { line: 2, error: RecordError::Truncated })));
+
+        let mut records = SliceRecords::new(b":0100000000FF\r\n");
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(
+            records.next(),
+            Some(Err(LineError { line: 2, error: RecordError::MissingEndOfFile }))
+        );
+        assert_eq!(records.next(), None);
+    }
+
+    #[test]
+    fn it_checks_the_end_of_a_slice() {
+        let mut records = SliceRecords::new(b":00000001FF\r\n \t\n\n");
+        assert_eq!(records.next(), Some(Ok((1, Record::EndOfFile))));
+        assert_eq!(records.check_end(), Ok(()));
+
+        let mut records = SliceRecords::new(b":00000001FF\n\n:00000001FF\n");
+        assert_eq!(records.next(), Some(Ok((1, Record::EndOfFile))));
+        assert_eq!(
+            records.check_end(),
+            Err(LineError { line: 3, error: RecordError::DataAfterEndOfFile })
+        );
+    }
+
+    #[test]
+    fn it_skips_comments_and_whitespace_in_lenient_mode() {
+        let hex = include_bytes!("../test/lenient.in");
+
+        let mut records = SliceRecords::new(hex);
+        assert_eq!(records.next(), Some(Err(LineError { line: 1, error: RecordError::MissingStartCode })));
+
+        let mut records = SliceRecords::with_mode(hex, LexMode::Lenient(Comments::all()));
+        assert!(matches!(records.next(), Some(Ok((6, Record::Data { addr: 0, .. })))));
+        assert!(matches!(records.next(), Some(Ok((7, Record::Data { addr: 1, .. })))));
+        assert_eq!(records.next(), Some(Ok((8, Record::EndOfFile))));
+        assert_eq!(records.warnings(), 7);
+        assert_eq!(records.check_end(), Ok(()));
+        assert_eq!(records.warnings(), 8);
+
+        let mut records = SliceRecords::with_mode(b"// comment\n:00000001FF\n", LexMode::Lenient(Comments::none()));
+        assert_eq!(records.next(), Some(Err(LineError { line: 1, error: RecordError::MissingStartCode })));
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{Read, Result};
+
+use crate::record::*;
+use crate::ringbuffer::RingBuffer;
+
+/// Pull-based parser yielding each record of an Intel HEX file together with
+/// its line number, starting at 1.
+///
+/// Iteration ends after the EndOfFile record.
+pub struct Records<R: Read> {
+    reader: R,
+    rb: RingBuffer<4096>,
+    line: usize,
+    done: bool,
+    mode: LexMode,
+    warnings: Warnings,
+}
+
+impl<R: Read> Records<R> {
+    pub fn new(reader: R) -> Records<R> {
+        Records::with_mode(reader, LexMode::Strict)
+    }
+
+    pub fn with_mode(reader: R, mode: LexMode) -> Records<R> {
+        Records {
+            reader,
+            rb: RingBuffer::new(),
+            line: 1,
+            done: false,
+            mode,
+            warnings: Warnings::default(),
+        }
+    }
+
+    /// Number of lines on which something was skipped in lenient mode
+    pub fn warnings(&self) -> usize {
+        self.warnings.count
+    }
+
+    /// Reads until `need` bytes are buffered, returns false if the reader
+    /// ran dry before that
+    fn fetch(&mut self, need: usize) -> Result<bool> {
+        while self.rb.len() < need {
+            if self.rb.fill(&mut self.reader)? == 0 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn error(&self, error: RecordError) -> LineError {
+        LineError { line: self.line, error }
+    }
+
+    /// Skips anything the lexer accepts at the start of the buffer other
+    /// than line endings, returns false if there is nothing to skip
+    fn skip_lenient(&mut self) -> Result<bool> {
+        let comments = match self.mode {
+            LexMode::Lenient(comments) => comments,
+            LexMode::Strict => return Ok(false),
+        };
+
+        let n = if self.fetch(2)? { 2 } else { 1 };
+        match comments.skip(self.rb.wrapping_peek(n).unwrap()) {
+            Skip::Char => self.rb.consume(1).unwrap(),
+            Skip::Line => {
+                while self.fetch(1)? && self.rb.peek(1).unwrap()[0] != b'\n' {
+                    self.rb.consume(1).unwrap();
+                }
+            }
+            Skip::No | Skip::NeedMore => return Ok(false),
+        }
+        self.warnings.warn(self.line);
+
+        Ok(true)
+    }
+
+    fn next_record(&mut self) -> Result<(usize, Record)> {
+        loop {
+            if !self.fetch(1)? {
+                return Err(self.error(RecordError::MissingEndOfFile).into());
+            }
+
+            let c = self.rb.peek(1).unwrap()[0];
+            match c {
+                b'\r' => {}
+                b'\n' => self.line += 1,
+                START_CODE => break,
+                _ if self.skip_lenient()? => continue,
+                _ => break,
+            }
+            self.rb.consume(1).unwrap();
+        }
+
+        if !self.fetch(HEADER_SZ)? {
+            return Err(self.error(RecordError::Truncated).into());
+        }
+        let line = self.line;
+        let header = self.rb.wrapping_peek(HEADER_SZ).unwrap();
+        let sz = record_size(header).map_err(|error| LineError { line, error })?;
+
+        if !self.fetch(sz)? {
+            return Err(self.error(RecordError::Truncated).into());
+        }
+        let buf = self.rb.wrapping_peek(sz).unwrap();
+        let record = decode_record(buf).map_err(|error| LineError { line, error })?;
+
+        self.rb.consume(sz).unwrap();
+
+        Ok((self.line, record))
+    }
+
+    /// Fails if anything but whitespace follows the records read so far,
+    /// meant to be called after the EndOfFile record
+    pub fn check_end(&mut self) -> Result<()> {
+        while self.fetch(1)? {
+            let c = self.rb.peek(1).unwrap()[0];
+            match c {
+                b'\n' => self.line += 1,
+                c if c.is_ascii_whitespace() => {}
+                _ if self.skip_lenient()? => continue,
+                _ => return Err(self.error(RecordError::DataAfterEndOfFile).into()),
+            }
+            self.rb.consume(1).unwrap();
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = Result<(usize, Record)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.next_record();
+        self.done = matches!(res, Err(_) | Ok((_, Record::EndOfFile)));
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::records::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn it_iterates_records_with_line_numbers() {
+        let hex = include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex");
+        let records: Vec<(usize, Record)> = Records::new(&hex[..])
+            .collect::<Result<_>>()
+            .expect("to parse the sniffer image");
+
+        assert_eq!(records.len(), 2178);
+        assert_eq!(records[0].0, 1);
+        assert!(matches!(records[0].1, Record::Data { addr: 0x0000, bytes } if bytes.len() == 16));
+        assert_eq!(records[2176], (2177, Record::StartSegmentAddr { cs: 0x0000, ip: 0x3605 }));
+        assert_eq!(records[2177], (2178, Record::EndOfFile));
+    }
+
+    #[test]
+    fn it_reports_the_line_of_a_bad_record() {
+        let hex = b":0100000000FF\r\n:0100010000FF\r\n:00000001FF\r\n";
+        let mut records = Records::new(&hex[..]);
+
+        assert!(records.next().unwrap().is_ok());
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().ends_with("on line 2"), "{}", err);
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn it_reports_input_ending_early() {
+        let mut records = Records::new(&b":0100000000FF\r\n"[..]);
+        assert!(records.next().unwrap().is_ok());
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Expected an EndOfFile record on line 2");
+
+        let mut records = Records::new(&b":0100000000FF\r\n:0100"[..]);
+        assert!(records.next().unwrap().is_ok());
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Record ends before its checksum on line 2");
+    }
+
+    #[test]
+    fn it_checks_the_end_of_the_input() {
+        let mut records = Records::new(&b":00000001FF\r\n\r\n  "[..]);
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.check_end().is_ok());
+
+        let mut records = Records::new(&b":00000001FF\n\ngarbage\n"[..]);
+        assert!(records.next().unwrap().is_ok());
+        let err = records.check_end().unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected data after the EndOfFile record on line 3");
+    }
+
+    #[test]
+    fn it_reads_like_slice_records_in_lenient_mode() {
+        let hex = include_bytes!("../test/lenient.in");
+        let mode = LexMode::Lenient(Comments::all());
+
+        let mut records = Records::with_mode(&hex[..], mode);
+        let mut slice_records = SliceRecords::with_mode(hex, mode);
+        for expected in &mut slice_records {
+            assert_eq!(records.next().unwrap().unwrap(), expected.unwrap());
+        }
+        assert!(records.next().is_none());
+        assert_eq!(records.warnings(), slice_records.warnings());
+        assert!(records.check_end().is_ok());
+    }
+}
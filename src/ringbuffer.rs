@@ -1,4 +1,3 @@
-use std::cmp;
 use std::io::Read;
 use std::io::Result;
 
@@ -84,14 +83,19 @@ impl<const SIZE: usize> RingBuffer<SIZE> {
 
     /// 2 copies will occur if this peek reaches of the buffer "wrap".
     /// One copy of the bytes present at the end of the buffer, and one
-    /// of the bytes present at the start of the buffer.
+    /// of the bytes present at the start of the buffer. Otherwise the
+    /// peek is copyless.
     pub fn wrapping_peek(&mut self, len: usize) -> RBResult<&[u8]> {
         if len > self.bytes_avail {
             return Err(RBErrorKind::NotEnoughBytesAvail);
         }
 
-        let end_bytes = cmp::min(SIZE - self.read_idx, len);
-        let start_bytes = self.bytes_avail - end_bytes;
+        if len <= SIZE - self.read_idx {
+            return Ok(&self.buf[self.read_idx..self.read_idx + len]);
+        }
+
+        let end_bytes = SIZE - self.read_idx;
+        let start_bytes = len - end_bytes;
 
         self.peek_buffer[..end_bytes]
             .copy_from_slice(&self.buf[self.read_idx..self.read_idx + end_bytes]);
@@ -0,0 +1,190 @@
+//! Vectorised hex decoding on x86_64, the instruction set is picked at
+//! runtime. Other targets use the scalar decoder.
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[cfg(not(target_arch = "x86_64"))]
+pub use crate::helpers::decode_hex_scalar as decode_hex;
+
+/// Same as `decode_hex_scalar`, using AVX2 or SSE2
+#[cfg(target_arch = "x86_64")]
+pub fn decode_hex(hex: &[u8], out: &mut [u8]) -> bool {
+    assert_eq!(hex.len(), 2 * out.len());
+
+    /* SSE2 is part of the x86_64 baseline */
+    unsafe {
+        if is_x86_feature_detected!("avx2") {
+            decode_hex_avx2(hex, out)
+        } else {
+            decode_hex_sse2(hex, out)
+        }
+    }
+}
+
+/* The vectors of digits are decoded like this, with wrapping arithmetic:
+ *
+ *   digit = c - '0'            is a digit if digit <= 9
+ *   alpha = (c | 0x20) - 'a'   is a letter if alpha <= 5, either case
+ *   value = digit or alpha + 10
+ *
+ * The digit pairs are then merged into bytes, `value[2i] << 4 | value[2i + 1]`.
+ *
+ * Input that does not fill the last vector is decoded with a vector ending
+ * at the end of the input instead, redoing some of the previous bytes.
+ */
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn decode_hex_avx2(hex: &[u8], out: &mut [u8]) -> bool {
+    /* Decodes the 32 digits at `hex` into 16 bytes at `out` */
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn decode_32(hex: *const u8, out: *mut u8) -> bool {
+        let c = _mm256_loadu_si256(hex as *const __m256i);
+
+        let digit = _mm256_sub_epi8(c, _mm256_set1_epi8(b'0' as i8));
+        let is_digit = _mm256_cmpeq_epi8(_mm256_min_epu8(digit, _mm256_set1_epi8(9)), digit);
+        let alpha = _mm256_sub_epi8(_mm256_or_si256(c, _mm256_set1_epi8(0x20)), _mm256_set1_epi8(b'a' as i8));
+        let is_alpha = _mm256_cmpeq_epi8(_mm256_min_epu8(alpha, _mm256_set1_epi8(5)), alpha);
+        let value = _mm256_blendv_epi8(_mm256_add_epi8(alpha, _mm256_set1_epi8(10)), digit, is_digit);
+
+        /* Each 16 bit lane holds a digit pair, the first digit in its low byte */
+        let bytes = _mm256_or_si256(
+            _mm256_slli_epi16(_mm256_and_si256(value, _mm256_set1_epi16(0xff)), 4),
+            _mm256_srli_epi16(value, 8),
+        );
+        /* Packing works within 128 bit halves, gather the two results */
+        let packed = _mm256_permute4x64_epi64(_mm256_packus_epi16(bytes, bytes), 0b1000);
+        _mm_storeu_si128(out as *mut __m128i, _mm256_castsi256_si128(packed));
+
+        _mm256_movemask_epi8(_mm256_or_si256(is_digit, is_alpha)) == -1
+    }
+
+    if out.len() < 16 {
+        return decode_hex_sse2(hex, out);
+    }
+
+    let mut i = 0;
+    while i + 16 <= out.len() {
+        if !decode_32(hex[2 * i..].as_ptr(), out[i..].as_mut_ptr()) {
+            return false;
+        }
+        i += 16;
+    }
+    if i < out.len() {
+        let i = out.len() - 16;
+        return decode_32(hex[2 * i..].as_ptr(), out[i..].as_mut_ptr());
+    }
+
+    true
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn decode_hex_sse2(hex: &[u8], out: &mut [u8]) -> bool {
+    /* Decodes the 16 digits at `hex` into 8 bytes at `out` */
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn decode_16(hex: *const u8, out: *mut u8) -> bool {
+        let c = _mm_loadu_si128(hex as *const __m128i);
+
+        let digit = _mm_sub_epi8(c, _mm_set1_epi8(b'0' as i8));
+        let is_digit = _mm_cmpeq_epi8(_mm_min_epu8(digit, _mm_set1_epi8(9)), digit);
+        let alpha = _mm_sub_epi8(_mm_or_si128(c, _mm_set1_epi8(0x20)), _mm_set1_epi8(b'a' as i8));
+        let is_alpha = _mm_cmpeq_epi8(_mm_min_epu8(alpha, _mm_set1_epi8(5)), alpha);
+        let value = _mm_or_si128(
+            _mm_and_si128(is_digit, digit),
+            _mm_andnot_si128(is_digit, _mm_add_epi8(alpha, _mm_set1_epi8(10))),
+        );
+
+        let bytes = _mm_or_si128(
+            _mm_slli_epi16(_mm_and_si128(value, _mm_set1_epi16(0xff)), 4),
+            _mm_srli_epi16(value, 8),
+        );
+        _mm_storel_epi64(out as *mut __m128i, _mm_packus_epi16(bytes, bytes));
+
+        _mm_movemask_epi8(_mm_or_si128(is_digit, is_alpha)) == 0xffff
+    }
+
+    if out.len() < 8 {
+        return crate::helpers::decode_hex_scalar(hex, out);
+    }
+
+    let mut i = 0;
+    while i + 8 <= out.len() {
+        if !decode_16(hex[2 * i..].as_ptr(), out[i..].as_mut_ptr()) {
+            return false;
+        }
+        i += 8;
+    }
+    if i < out.len() {
+        let i = out.len() - 8;
+        return decode_16(hex[2 * i..].as_ptr(), out[i..].as_mut_ptr());
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::decode_hex_scalar;
+    use crate::simd::*;
+
+    /// Hex digits of both cases, from a simple LCG
+    fn digits(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b"0123456789abcdefABCDEF"[(state >> 16) as usize % 22]
+            })
+            .collect()
+    }
+
+    fn assert_same_as_scalar(hex: &[u8]) {
+        let mut expected = vec![0u8; hex.len() / 2];
+        let valid = decode_hex_scalar(hex, &mut expected);
+
+        let mut out = vec![0u8; hex.len() / 2];
+        assert_eq!(decode_hex(hex, &mut out), valid, "{:?}", hex);
+        if valid {
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn it_decodes_like_the_scalar_version() {
+        for len in 0..=130 {
+            assert_same_as_scalar(&digits(2 * len, len as u32));
+        }
+        assert_same_as_scalar(&digits(2 * 260, 7));
+    }
+
+    #[test]
+    fn it_rejects_any_other_byte_anywhere() {
+        let valid = digits(70, 3);
+
+        for pos in 0..valid.len() {
+            for c in 0..=255u8 {
+                let mut hex = valid.clone();
+                hex[pos] = c;
+                assert_same_as_scalar(&hex);
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn it_decodes_with_sse2_only() {
+        for len in [0, 7, 8, 9, 15, 64, 255] {
+            let hex = digits(2 * len, 11);
+            let mut expected = vec![0u8; len];
+            decode_hex_scalar(&hex, &mut expected);
+
+            let mut out = vec![0u8; len];
+            assert!(unsafe { decode_hex_sse2(&hex, &mut out) });
+            assert_eq!(out, expected);
+        }
+    }
+}
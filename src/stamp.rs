@@ -0,0 +1,276 @@
+use std::cmp;
+use std::io::{self, Read, Result, Write};
+use std::str::FromStr;
+
+use crate::datawriter::{DataWriter, StartAddr};
+use crate::hash::{hash_slice, Algorithm};
+use crate::pipeline::{convert_records, BinOptions, ConvertWarnings};
+use crate::record::SliceRecords;
+
+/// Bytes per row handed to the data writer
+const ROW_SZ: u64 = 16;
+
+/// Byte order a CRC is stamped in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Endian, String> {
+        match s {
+            "little" | "le" => Ok(Endian::Little),
+            "big" | "be" => Ok(Endian::Big),
+            _ => Err(format!("Expected little or big, found {:?}", s)),
+        }
+    }
+}
+
+/// What `stamp` computes and where it writes it
+#[derive(Debug, Clone)]
+pub struct StampOptions {
+    pub algorithm: Algorithm,
+    /// The image hashed, as for `hash`. It needs ranges, which must not
+    /// include the stamped addresses.
+    pub hash: BinOptions,
+    /// Address the CRC or digest is written at
+    pub addr: u32,
+    /// Byte order of a CRC, digests are written as they are
+    pub endian: Endian,
+}
+
+/// Replaces the data at an address with the stamp. The stamp is written
+/// where that data was or, without any, before the first data following it.
+struct StampDataWriter<'a, DW> {
+    inner: &'a mut DW,
+    addr: u64,
+    end: u64,
+    /// The bytes to stamp, empty once written
+    bytes: Vec<u8>,
+    /// End of the last piece passed on, `None` if the inner segment is closed
+    next_addr: Option<u64>,
+}
+
+impl<'a, DW> StampDataWriter<'a, DW> {
+    fn new(inner: &'a mut DW, addr: u32, bytes: Vec<u8>) -> StampDataWriter<'a, DW> {
+        StampDataWriter {
+            inner,
+            addr: addr as u64,
+            end: addr as u64 + bytes.len() as u64,
+            bytes,
+            next_addr: None,
+        }
+    }
+
+    fn put<W: Write>(&mut self, writer: &mut W, addr: u64, bytes: &[u8]) -> Result<()>
+    where
+        DW: DataWriter<W>,
+    {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        match self.next_addr {
+            Some(next) if next == addr => {}
+            Some(_) => {
+                self.inner.end_segment(writer)?;
+                self.inner.begin_segment(writer, addr as u32)?;
+            }
+            None => self.inner.begin_segment(writer, addr as u32)?,
+        }
+
+        self.inner.write(writer, addr as u32, bytes)?;
+        self.next_addr = Some(addr + bytes.len() as u64);
+        Ok(())
+    }
+
+    /// Writes the stamp in rows, unless that was done already
+    fn stamp<W: Write>(&mut self, writer: &mut W) -> Result<()>
+    where
+        DW: DataWriter<W>,
+    {
+        let bytes = std::mem::take(&mut self.bytes);
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let addr = self.addr + pos as u64;
+            let n = cmp::min(bytes.len() - pos, (ROW_SZ - addr % ROW_SZ) as usize);
+            self.put(writer, addr, &bytes[pos..pos + n])?;
+            pos += n;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, DW: DataWriter<W>> DataWriter<W> for StampDataWriter<'_, DW> {
+    fn write(&mut self, writer: &mut W, addr: u32, bytes: &[u8]) -> Result<()> {
+        let start = addr as u64;
+        let end = start + bytes.len() as u64;
+
+        if start < self.addr {
+            let n = (cmp::min(end, self.addr) - start) as usize;
+            self.put(writer, start, &bytes[..n])?;
+        }
+        if end > self.addr {
+            self.stamp(writer)?;
+        }
+        if end > self.end {
+            let from = cmp::max(start, self.end);
+            self.put(writer, from, &bytes[(from - start) as usize..])?;
+        }
+
+        Ok(())
+    }
+
+    fn end_segment(&mut self, writer: &mut W) -> Result<()> {
+        match self.next_addr.take() {
+            Some(_) => self.inner.end_segment(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn start_addr(&mut self, writer: &mut W, start_addr: StartAddr) -> Result<()> {
+        self.inner.start_addr(writer, start_addr)
+    }
+
+    fn finish(&mut self, writer: &mut W) -> Result<()> {
+        self.stamp(writer)?;
+        DataWriter::<W>::end_segment(self, writer)?;
+        self.inner.finish(writer)
+    }
+}
+
+/// Hashes the image like `hash` and feeds `input` to `data_writer` with the
+/// CRC or digest stamped at `opts.addr`, replacing any data there, in the
+/// way `convert_slice` does. Returns the bytes stamped and the warnings of
+/// the conversion.
+pub fn stamp_slice<W: Write, DWR: DataWriter<W>>(
+    input: &[u8],
+    writer: W,
+    data_writer: &mut DWR,
+    opts: &StampOptions,
+) -> Result<(Vec<u8>, ConvertWarnings)> {
+    let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    if opts.hash.ranges.is_empty() {
+        return invalid("Stamping needs the address ranges to hash".into());
+    }
+
+    let (mut bytes, warnings) = hash_slice(input, opts.algorithm, &opts.hash)?;
+    if matches!(opts.algorithm, Algorithm::Crc(_)) && opts.endian == Endian::Little {
+        bytes.reverse();
+    }
+
+    let (start, end) = (opts.addr as u64, opts.addr as u64 + bytes.len() as u64);
+    if end > 1 << 32 {
+        return invalid(format!(
+            "{} bytes at {:#010x} exceed the 32-bit address space",
+            bytes.len(),
+            opts.addr
+        ));
+    }
+    if let Some(r) = opts
+        .hash
+        .ranges
+        .iter()
+        .find(|r| (r.start as u64) < end && start < r.end)
+    {
+        return invalid(format!(
+            "The stamp at {:#010x}..{:#010x} lies within the hashed range {:#010x}..{:#010x}",
+            start, end, r.start, r.end
+        ));
+    }
+
+    /* The input converted again warns about the same as when hashing */
+    let checks = opts.hash.checks();
+    let mut stamped = StampDataWriter::new(data_writer, opts.addr, bytes.clone());
+    convert_records(
+        SliceRecords::with_mode(input, checks.lex_mode),
+        writer,
+        &mut stamped,
+        checks,
+    )?;
+
+    Ok((bytes, warnings))
+}
+
+/// Same as `stamp_slice`, reading the whole input into memory first
+pub fn stamp<R: Read, W: Write, DWR: DataWriter<W>>(
+    mut reader: R,
+    writer: W,
+    data_writer: &mut DWR,
+    opts: &StampOptions,
+) -> Result<(Vec<u8>, ConvertWarnings)> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    stamp_slice(&input, writer, data_writer, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datawriter::BinDataWriter;
+    use crate::hash::CrcParams;
+    use crate::pipeline::AddrRange;
+    use crate::stamp::*;
+
+    fn crc32_opts(ranges: Vec<AddrRange>, addr: u32) -> StampOptions {
+        StampOptions {
+            algorithm: Algorithm::Crc(CrcParams::CRC32),
+            hash: BinOptions {
+                ranges,
+                ..BinOptions::default()
+            },
+            addr,
+            endian: Endian::Little,
+        }
+    }
+
+    #[test]
+    fn it_stamps_a_crc_after_the_hashed_range() {
+        let hex = b":0400000001020304F2\n:00000001FF\n";
+        let opts = crc32_opts(vec![AddrRange { start: 0, end: 8 }], 8);
+        let mut bin = Vec::new();
+
+        let (stamped, _) =
+            stamp_slice(&hex[..], &mut bin, &mut BinDataWriter::new(0xff), &opts).unwrap();
+
+        let mut image = vec![1, 2, 3, 4, 0xff, 0xff, 0xff, 0xff];
+        let mut crc = crate::Crc::new(CrcParams::CRC32).unwrap();
+        crc.update(&image);
+        assert_eq!(stamped, crc.finish().to_le_bytes());
+        image.extend_from_slice(&stamped);
+        assert_eq!(bin, image);
+    }
+
+    #[test]
+    fn it_replaces_the_data_at_the_stamp() {
+        let hex = b":080000000102030405060708D4\n:00000001FF\n";
+        let mut opts = crc32_opts(vec![AddrRange { start: 0, end: 2 }], 3);
+        opts.algorithm = Algorithm::Crc(CrcParams::CRC16_CCITT);
+        opts.endian = Endian::Big;
+        let mut bin = Vec::new();
+
+        let (stamped, _) =
+            stamp_slice(&hex[..], &mut bin, &mut BinDataWriter::new(0xff), &opts).unwrap();
+
+        let mut crc = crate::Crc::new(CrcParams::CRC16_CCITT).unwrap();
+        crc.update(&[1, 2]);
+        assert_eq!(stamped, (crc.finish() as u16).to_be_bytes());
+        assert_eq!(bin, [1, 2, 3, stamped[0], stamped[1], 6, 7, 8]);
+    }
+
+    #[test]
+    fn it_refuses_to_stamp_within_the_hashed_range() {
+        let hex = b":0400000001020304F2\n:00000001FF\n";
+        let opts = crc32_opts(vec![AddrRange { start: 0, end: 8 }], 4);
+
+        let err =
+            stamp_slice(&hex[..], Vec::new(), &mut BinDataWriter::new(0xff), &opts).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let opts = crc32_opts(Vec::new(), 8);
+        assert!(stamp_slice(&hex[..], Vec::new(), &mut BinDataWriter::new(0xff), &opts).is_err());
+    }
+}
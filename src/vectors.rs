@@ -0,0 +1,232 @@
+use std::fmt;
+
+use crate::datawriter::StartAddr;
+use crate::pipeline::AddrRange;
+
+/// SRAM region of the Cortex-M memory map, where the initial stack pointer
+/// is expected unless other RAM is given
+pub const CORTEX_M_SRAM: AddrRange = AddrRange {
+    start: 0x2000_0000,
+    end: 0x4000_0000,
+};
+
+/// Problems with the first two entries of a Cortex-M vector table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorProblem {
+    /// The image holds no data for the entry at this address
+    Missing(u32),
+    /// The reset handler address has bit 0 clear
+    ResetNotThumb(u32),
+    /// The reset handler lies outside the data of the image
+    ResetOutsideData(u32),
+    /// The initial stack pointer points outside RAM
+    SpOutsideRam(u32),
+    /// The initial stack pointer is not 4 byte aligned
+    SpUnaligned(u32),
+    /// The start address record names another entry point
+    StartAddrMismatch { reset: u32, start: u32 },
+}
+
+impl fmt::Display for VectorProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VectorProblem::Missing(addr) => {
+                write!(f, "No data for the vector table entry at {:#010x}", addr)
+            }
+            VectorProblem::ResetNotThumb(reset) => write!(
+                f,
+                "Reset handler {:#010x} is not a Thumb address, bit 0 is clear",
+                reset
+            ),
+            VectorProblem::ResetOutsideData(reset) => {
+                write!(f, "Reset handler {:#010x} lies outside the data", reset)
+            }
+            VectorProblem::SpOutsideRam(sp) => {
+                write!(f, "Initial SP {:#010x} points outside RAM", sp)
+            }
+            VectorProblem::SpUnaligned(sp) => {
+                write!(f, "Initial SP {:#010x} is not 4 byte aligned", sp)
+            }
+            VectorProblem::StartAddrMismatch { reset, start } => write!(
+                f,
+                "Reset handler {:#010x} differs from the start address {:#010x}",
+                reset, start
+            ),
+        }
+    }
+}
+
+/// The initial stack pointer and reset vector of a Cortex-M vector table,
+/// see `InfoOptions::check_vectors`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorTable {
+    pub base: u32,
+    /// `None` if the image holds no data for the entry
+    pub initial_sp: Option<u32>,
+    /// `None` if the image holds no data for the entry
+    pub reset: Option<u32>,
+    pub problems: Vec<VectorProblem>,
+}
+
+impl VectorTable {
+    /// Checks the entries in `bytes`, the image's data at `base` where it
+    /// has any. `segments` are the image's (address, length) data regions.
+    pub(crate) fn check(
+        base: u32,
+        bytes: &[Option<u8>; 8],
+        segments: &[(u32, u64)],
+        start_addr: Option<StartAddr>,
+        ram: &[AddrRange],
+    ) -> VectorTable {
+        let word = |i: usize| -> Option<u32> {
+            let mut word = [0u8; 4];
+            for (b, &byte) in word.iter_mut().zip(&bytes[4 * i..4 * i + 4]) {
+                *b = byte?;
+            }
+            Some(u32::from_le_bytes(word))
+        };
+        let (initial_sp, reset) = (word(0), word(1));
+        let mut problems = Vec::new();
+
+        match initial_sp {
+            None => problems.push(VectorProblem::Missing(base)),
+            Some(sp) => {
+                /* The stack grows down from its initial value, which may be the end of RAM */
+                let default = [CORTEX_M_SRAM];
+                let ram = if ram.is_empty() { &default[..] } else { ram };
+                if !ram.iter().any(|r| r.start < sp && sp as u64 <= r.end) {
+                    problems.push(VectorProblem::SpOutsideRam(sp));
+                }
+                if sp % 4 != 0 {
+                    problems.push(VectorProblem::SpUnaligned(sp));
+                }
+            }
+        }
+
+        match reset {
+            None => problems.push(VectorProblem::Missing(base.wrapping_add(4))),
+            Some(reset) => {
+                if reset & 1 == 0 {
+                    problems.push(VectorProblem::ResetNotThumb(reset));
+                }
+                let addr = (reset & !1) as u64;
+                if !segments
+                    .iter()
+                    .any(|&(start, len)| start as u64 <= addr && addr < start as u64 + len)
+                {
+                    problems.push(VectorProblem::ResetOutsideData(reset));
+                }
+
+                /* Entry points are compared without the Thumb bit, which not every tool sets */
+                let start = match start_addr {
+                    Some(StartAddr::Linear(eip)) => Some(eip),
+                    Some(StartAddr::Segment { cs, ip }) => Some(((cs as u32) << 4) + ip as u32),
+                    None => None,
+                };
+                if let Some(start) = start.filter(|&start| start & !1 != reset & !1) {
+                    problems.push(VectorProblem::StartAddrMismatch { reset, start });
+                }
+            }
+        }
+
+        VectorTable {
+            base,
+            initial_sp,
+            reset,
+            problems,
+        }
+    }
+}
+
+impl fmt::Display for VectorTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "vector table:       {:#010x}", self.base)?;
+        for &(name, entry) in [
+            ("initial SP", self.initial_sp),
+            ("reset handler", self.reset),
+        ]
+        .iter()
+        {
+            match entry {
+                Some(value) => writeln!(f, "  {:<18}{:#010x}", name, value)?,
+                None => writeln!(f, "  {:<18}missing", name)?,
+            }
+        }
+        if self.problems.is_empty() {
+            writeln!(f, "  checks            passed")?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "  problem           {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vectors::*;
+
+    fn bytes(sp: u32, reset: u32) -> [Option<u8>; 8] {
+        let mut bytes = [None; 8];
+        for (b, &byte) in bytes
+            .iter_mut()
+            .zip(sp.to_le_bytes().iter().chain(&reset.to_le_bytes()))
+        {
+            *b = Some(byte);
+        }
+        bytes
+    }
+
+    #[test]
+    fn it_passes_a_plausible_vector_table() {
+        let table = VectorTable::check(
+            0x8000,
+            &bytes(0x2001_0000, 0x8101),
+            &[(0x8000, 0x1000)],
+            Some(StartAddr::Linear(0x8101)),
+            &[],
+        );
+
+        assert_eq!(table.initial_sp, Some(0x2001_0000));
+        assert_eq!(table.reset, Some(0x8101));
+        assert!(table.problems.is_empty());
+    }
+
+    #[test]
+    fn it_finds_every_problem() {
+        let table = VectorTable::check(
+            0,
+            &bytes(0x1000_0002, 0x9000),
+            &[(0, 0x1000)],
+            Some(StartAddr::Linear(0x101)),
+            &[AddrRange {
+                start: 0x1000_0000,
+                end: 0x1000_1000,
+            }],
+        );
+
+        assert_eq!(
+            table.problems,
+            vec![
+                VectorProblem::SpUnaligned(0x1000_0002),
+                VectorProblem::ResetNotThumb(0x9000),
+                VectorProblem::ResetOutsideData(0x9000),
+                VectorProblem::StartAddrMismatch {
+                    reset: 0x9000,
+                    start: 0x101
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_missing_entries() {
+        let mut data = bytes(0x2000_1000, 0x101);
+        data[5] = None;
+
+        let table = VectorTable::check(0x100, &data, &[(0x100, 5)], None, &[]);
+
+        assert_eq!(table.reset, None);
+        assert_eq!(table.problems, vec![VectorProblem::Missing(0x104)]);
+    }
+}
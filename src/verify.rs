@@ -0,0 +1,311 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Result};
+
+use crate::chunks::AddressResolver;
+use crate::overlaps::{Overlap, OverlapChecker};
+use crate::record::{decode_record, record_size, Record, RecordError, HEADER_SZ, START_CODE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Problems `verify` looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issue {
+    /// The record on the line can not be decoded
+    Record(RecordError),
+    /// Characters follow the checksum on the same line
+    TrailingCharacters,
+    /// Another EndOfFile record after the one on `first_line`
+    DuplicateEndOfFile { first_line: usize },
+    /// Anything but blank lines after the EndOfFile record
+    DataAfterEndOfFile,
+    /// Data at `[start, end)` was also written by the record on
+    /// `other_line`, with the same bytes if `identical`
+    Overlap { start: u32, end: u64, other_line: usize, identical: bool },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::Overlap { identical: true, .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Record(e) => write!(f, "{}", e),
+            Issue::TrailingCharacters => write!(f, "Unexpected characters after the checksum"),
+            Issue::DuplicateEndOfFile { first_line } => {
+                write!(f, "Another EndOfFile record, the first is on line {}", first_line)
+            }
+            Issue::DataAfterEndOfFile => write!(f, "Data after the EndOfFile record"),
+            Issue::Overlap { start, end, other_line, identical: true } => write!(
+                f,
+                "Data at {:#010x}..{:#010x} repeats the data on line {}",
+                start, end, other_line
+            ),
+            Issue::Overlap { start, end, other_line, identical: false } => write!(
+                f,
+                "Data at {:#010x}..{:#010x} conflicts with the data on line {}",
+                start, end, other_line
+            ),
+        }
+    }
+}
+
+/// An `Issue` and the line it was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Problem {
+    pub line: usize,
+    pub issue: Issue,
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        self.issue.severity()
+    }
+}
+
+impl From<Overlap> for Problem {
+    fn from(o: Overlap) -> Problem {
+        Problem {
+            line: o.line,
+            issue: Issue::Overlap { start: o.start, end: o.end, other_line: o.other_line, identical: o.identical },
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on line {}", self.issue, self.line)
+    }
+}
+
+/// Outcome of `verify`, problems are ordered by line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.problems.iter().filter(|p| p.severity() == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.problems.iter().filter(|p| p.severity() == Severity::Warning).count()
+    }
+}
+
+/// Decodes a line holding exactly one record, returns the record even if
+/// the line goes on after it
+fn check_line(line: &[u8]) -> std::result::Result<(Record, Option<Issue>), Issue> {
+    if line.first() != Some(&START_CODE) {
+        return Err(Issue::Record(RecordError::MissingStartCode));
+    }
+    if line.len() < HEADER_SZ {
+        return Err(Issue::Record(RecordError::Truncated));
+    }
+    let sz = record_size(&line[..HEADER_SZ]).map_err(Issue::Record)?;
+    if line.len() < sz {
+        return Err(Issue::Record(RecordError::Truncated));
+    }
+    let record = decode_record(&line[..sz]).map_err(Issue::Record)?;
+
+    Ok((record, Some(Issue::TrailingCharacters).filter(|_| line.len() > sz)))
+}
+
+/// Checks every line of an Intel HEX file and reports all problems found,
+/// rather than stopping at the first one like the conversions do.
+///
+/// Besides the checks made when decoding records, each line must hold
+/// exactly one record and the file must end with a single EndOfFile record.
+/// Data records writing the same address as an earlier one are warned about
+/// if they write the same bytes, and are errors otherwise.
+pub fn verify<R: Read>(reader: R) -> Result<Report> {
+    let mut reader = BufReader::new(reader);
+    let mut report = Report::default();
+    let mut resolver = AddressResolver::new();
+    let mut checker = OverlapChecker::new();
+    let mut overlaps = Vec::new();
+    let mut eof_line = None;
+    let mut after_eof_reported = false;
+
+    let mut buf = Vec::new();
+    let mut line = 0;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        line += 1;
+
+        let mut text = &buf[..];
+        while let Some((b'\n' | b'\r', rest)) = text.split_last() {
+            text = rest;
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let mut report_issue = |issue| report.problems.push(Problem { line, issue });
+
+        if let Some(first_line) = eof_line {
+            match check_line(text) {
+                Ok((Record::EndOfFile, _)) => report_issue(Issue::DuplicateEndOfFile { first_line }),
+                _ if !after_eof_reported => {
+                    report_issue(Issue::DataAfterEndOfFile);
+                    after_eof_reported = true;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let record = match check_line(text) {
+            Ok((record, trailing)) => {
+                if let Some(issue) = trailing {
+                    report_issue(issue);
+                }
+                record
+            }
+            Err(issue) => {
+                report_issue(issue);
+                continue;
+            }
+        };
+
+        match record {
+            Record::Data { addr, bytes } => {
+                for (addr, piece) in resolver.resolve(addr, &bytes) {
+                    checker.write(line, addr, piece, &mut overlaps);
+                }
+            }
+            Record::EndOfFile => eof_line = Some(line),
+            _ => {
+                resolver.apply(&record);
+            }
+        }
+    }
+
+    if eof_line.is_none() {
+        report.problems.push(Problem {
+            line: line + 1,
+            issue: Issue::Record(RecordError::MissingEndOfFile),
+        });
+    }
+
+    report.problems.extend(overlaps.into_iter().map(Problem::from));
+    report.problems.sort_by_key(|p| p.line);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::verify::*;
+
+    #[test]
+    fn it_finds_no_problems_in_valid_files() {
+        for hex in [
+            &include_bytes!("../hex-examples/sniffer_nrf52840dk_nrf52840_7cc811f.hex")[..],
+            &include_bytes!("../test/addrspace-gap-mid.in")[..],
+        ] {
+            assert_eq!(verify(hex).unwrap(), Report::default());
+        }
+    }
+
+    #[test]
+    fn it_reports_all_problems() {
+        let hex = b":0100000000FF\r\n\
+                    :0100010000FF\r\n\
+                    garbage\n\
+                    :0100\n\
+                    :0100020011EC  \n\
+                    :01000000AA55\n\
+                    :0100030600F6\n\
+                    \n\
+                    :00000001FF\n\
+                    :00000001FF\n\
+                    :0100020011EC\n\
+                    :00000001FF\n";
+
+        let report = verify(&hex[..]).unwrap();
+
+        let problems: Vec<(usize, Issue)> = report.problems.iter().map(|p| (p.line, p.issue)).collect();
+        assert_eq!(
+            problems,
+            vec![
+                (2, Issue::Record(RecordError::ChecksumMismatch { expected: 0xfe, found: 0xff })),
+                (3, Issue::Record(RecordError::MissingStartCode)),
+                (4, Issue::Record(RecordError::Truncated)),
+                (5, Issue::TrailingCharacters),
+                (6, Issue::Overlap { start: 0, end: 1, other_line: 1, identical: false }),
+                (7, Issue::Record(RecordError::UnknownType(6))),
+                (10, Issue::DuplicateEndOfFile { first_line: 9 }),
+                (11, Issue::DataAfterEndOfFile),
+                (12, Issue::DuplicateEndOfFile { first_line: 9 }),
+            ]
+        );
+        assert_eq!((report.errors(), report.warnings()), (9, 0));
+    }
+
+    #[test]
+    fn it_reports_a_missing_end_of_file() {
+        let report = verify(&b":0100000000FF\n:0100010000FE\n"[..]).unwrap();
+
+        assert_eq!(
+            report.problems,
+            vec![Problem { line: 3, issue: Issue::Record(RecordError::MissingEndOfFile) }]
+        );
+    }
+
+    #[test]
+    fn it_reports_overlaps_with_both_lines() {
+        let hex = b":0400000001020304F2\n:020000040000FA\n:02000200AABB97\n:00000001FF\n";
+
+        let report = verify(&hex[..]).unwrap();
+
+        assert_eq!(
+            report.problems,
+            vec![Problem {
+                line: 3,
+                issue: Issue::Overlap { start: 2, end: 4, other_line: 1, identical: false }
+            }]
+        );
+    }
+
+    #[test]
+    fn it_warns_about_repeated_data() {
+        let hex = b":0400000001020304F2\n:020002000304F5\n:00000001FF\n";
+
+        let report = verify(&hex[..]).unwrap();
+
+        assert_eq!(
+            report.problems,
+            vec![Problem {
+                line: 2,
+                issue: Issue::Overlap { start: 2, end: 4, other_line: 1, identical: true }
+            }]
+        );
+        assert_eq!((report.errors(), report.warnings()), (0, 1));
+        assert_eq!(
+            report.problems[0].to_string(),
+            "Data at 0x00000002..0x00000004 repeats the data on line 1 on line 2"
+        );
+    }
+}
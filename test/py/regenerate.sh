@@ -0,0 +1,9 @@
+#!/bin/sh
+# Regenerates the truth files of the python compatible dump with hex2dump.py
+# of python-intelhex, e.g. after `pip install intelhex==2.3.0`
+set -e
+cd "$(dirname "$0")"
+for truth in *.truth; do
+    name=${truth%.truth}
+    hex2dump.py "../$name.in" > "$truth"
+done